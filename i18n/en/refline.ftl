@@ -8,4 +8,120 @@ reference_store = Reference Store
 add_refs = Add reference pictures first
 loading = Loading resources. Relax a moment.
 add_source = Add source
+add_archive = Add archive
+archives = Zip and comic book archives
 remove_source = Remove Source
+source_offline = Offline, showing the last scan until it is available again
+relocate_source = Location
+relocate = Move to…
+already_a_source = { $path } is already a source
+relocation_complete = All { $files } files were found at the new location
+relocation_mismatches = { $found } of { $files } files were found at the new location, { $missing } are missing and { $changed } changed
+missing = Missing
+changed = Changed
+more_mismatches = …and { $count } more
+
+formats = Formats
+play = Play
+pause = Pause
+sequences = Sequences
+scan_options = Scanning
+exclude_patterns = Exclude, e.g. _unused/,*.tmp
+include_patterns = Include anyway
+max_depth = Depth
+unlimited = unlimited
+min_file_size = Min KiB
+follow_links = Follow links
+apply_scan_options = Rescan
+frame = Frame
+settings = Settings
+decode_limits = Decode limits
+max_image_megapixels = Decode jpegs and pngs larger than { $megapixels } megapixels downsampled
+max_decode_memory = Skip images that need more than { $mib } MiB to decode
+downsampled = Shown downsampled, the original has { $width }x{ $height } pixels
+load_failed = Could not load { $path }: { $error }
+all_sources = All sources
+no_references = No references found
+back = Back
+add_tag = Add tag
+add = Add
+favorite = Favorite
+notes = Notes
+query_placeholder = Filter, e.g. pose:standing AND -clothed rating>=3 source:"gesture"
+save_as_collection = Save as collection
+new_collection = Collection { $number }
+collection_name = Name
+remove_collection = Remove collection
+use_for_session = Use for figure drawing
+all_references = All references
+sort = Sort
+sort_path = Path
+sort_name = Name
+sort_newest = Newest
+sort_oldest = Oldest
+sort_largest = Largest
+album = Album
+new_album = Album { $number }
+new_album_button = New album
+album_name = Name
+add_to_album = Add to album (a)
+remove_from_album = Remove from album (a)
+no_active_album = Choose or create an album first
+album_to_board = Open on reference board
+remove_album = Remove album
+empty_album = Add references with the a key on the figure drawing page or in the reference store
+export = Export
+export_browser = { $count } shown references
+export_symlink = Symbolic links
+export_copy = Copies
+export_keep_names = Keep folders
+export_flatten = Flatten
+export_numbered = Numbered
+export_to_folder = Export to folder…
+exporting = Exporting…
+exported = Exported { $exported } files, { $failed } failed
+export_failed = Export failed: { $error }
+nothing_to_export = Nothing to export
+hide = Never show again (x)
+restore = Restore (x)
+show_hidden = Hidden
+content = Content
+content_folder = Folder default
+content_general = General
+content_artistic = Artistic nudity
+content_explicit = Explicit
+max_content = Show up to
+set_content_of_shown = Set content of all shown references
+duplicates = { $count ->
+    [0] No duplicates found
+    [one] 1 group of duplicates
+   *[other] { $count } groups of duplicates
+}
+duplicates_skipped = { $count ->
+    [one] 1 reference is left out of sessions
+   *[other] { $count } references are left out of sessions
+}
+scanning_source = Scanning { $source }: { $files ->
+    [one] 1 reference
+   *[other] { $files } references
+} found
+scans_queued = { $count ->
+    [one] 1 more source queued
+   *[other] { $count } more sources queued
+}
+cancel_scan = Stop scanning
+searching_duplicates = Searching for duplicates…
+find_duplicates = Search again
+hide_duplicate = Never show again
+restore_duplicate = Restore
+close_comparison = Close
+similar = More like this
+more_like_this = More like this (m)
+no_similar_search = Press m on a reference to find references that look like it
+searching_similar = Searching…
+similar_results = { $count ->
+    [0] No similar references found
+    [one] 1 similar reference
+   *[other] { $count } similar references
+}
+similar_to_board = Open best results on reference board
@@ -0,0 +1,117 @@
+//! Hand curated albums of references, which may span multiple source folders.
+
+use std::path::{Path, PathBuf};
+
+use crate::{io, reference};
+
+pub const ALBUMS_FILENAME: &str = "albums.ron";
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct Albums {
+    pub albums: Vec<Album>,
+    /// Id of the album references are added to with a single key press.
+    #[serde(default)]
+    pub active: Option<u32>,
+}
+impl Albums {
+    pub fn try_load() -> Option<Albums> {
+        io::try_load(ALBUMS_FILENAME)
+    }
+    pub fn save_to_disk(&self) -> Option<()> {
+        io::save_to_disk(self, ALBUMS_FILENAME)
+    }
+    pub fn get(&self, id: u32) -> Option<&Album> {
+        self.albums.iter().find(|album| album.id == id)
+    }
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Album> {
+        self.albums.iter_mut().find(|album| album.id == id)
+    }
+    pub fn active_album(&self) -> Option<&Album> {
+        self.active.and_then(|id| self.get(id))
+    }
+    /// Adds an empty album, makes it the active one and returns its id.
+    pub fn add(&mut self, name: String) -> u32 {
+        let id = self
+            .albums
+            .iter()
+            .map(|album| album.id + 1)
+            .max()
+            .unwrap_or_default();
+        self.albums.push(Album {
+            id,
+            name,
+            references: Vec::new(),
+        });
+        self.active = Some(id);
+        self.save_to_disk();
+        id
+    }
+    /// Updates the references of all albums after files or folders were moved from `from` to `to`.
+    /// References below the `nested` source folders are not part of the move and keep their path.
+    /// Returns whether any album changed.
+    pub fn rename(&mut self, from: &Path, to: &Path, nested: &[PathBuf]) -> bool {
+        let mut changed = false;
+        for reference in self
+            .albums
+            .iter_mut()
+            .flat_map(|album| &mut album.references)
+            .filter(|reference| !nested.iter().any(|source| reference.starts_with(source)))
+        {
+            if let Some(new_path) = reference::rebase(reference, from, to) {
+                *reference = new_path;
+                changed = true;
+            }
+        }
+        changed
+    }
+    pub fn remove(&mut self, id: u32) {
+        self.albums.retain(|album| album.id != id);
+        if self.active == Some(id) {
+            self.active = None;
+        }
+        self.save_to_disk();
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Album {
+    /// Stays the same when the album is renamed.
+    pub id: u32,
+    pub name: String,
+    /// Paths of the references in the order chosen by the user.
+    pub references: Vec<PathBuf>,
+}
+impl Album {
+    pub fn contains(&self, path: &Path) -> bool {
+        self.references.iter().any(|reference| reference == path)
+    }
+    pub fn apply(&mut self, edit: AlbumEdit) {
+        match edit {
+            AlbumEdit::Rename(name) => self.name = name,
+            AlbumEdit::Add(path) => {
+                if !self.contains(&path) {
+                    self.references.push(path);
+                }
+            }
+            AlbumEdit::Remove(path) => self.references.retain(|reference| reference != &path),
+            AlbumEdit::Move { from, to } => {
+                if from < self.references.len() && to < self.references.len() {
+                    let reference = self.references.remove(from);
+                    self.references.insert(to, reference);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum AlbumEdit {
+    Rename(String),
+    Add(PathBuf),
+    Remove(PathBuf),
+    /// Moves the reference at index `from` to index `to`.
+    Move {
+        from: usize,
+        to: usize,
+    },
+}
@@ -0,0 +1,210 @@
+//! Playback of references that consist of multiple frames, like animated gifs.
+
+use std::time::Duration;
+
+use cosmic::{
+    iced::{Alignment, Length},
+    iced_widget::button,
+    widget::{self, image::Handle, pane_grid::Pane, text},
+    Element,
+};
+
+use crate::{
+    app::Message,
+    decode::{DecodeWarning, Decoded},
+    fl,
+};
+
+/// Interval in which running animations are advanced.
+pub const TICK: Duration = Duration::from_millis(20);
+pub const MIN_SPEED: f32 = 0.1;
+pub const MAX_SPEED: f32 = 4.0;
+
+/// A decoded reference which is ready to be displayed.
+#[derive(Debug, Clone)]
+pub struct LoadedReference {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<LoadedFrame>,
+    pub warning: Option<DecodeWarning>,
+    /// Memory used by the pixels of all frames.
+    pub bytes: usize,
+}
+#[derive(Debug, Clone)]
+pub struct LoadedFrame {
+    pub handle: Handle,
+    pub delay: Duration,
+}
+impl LoadedReference {
+    /// Returns `None` if no frame was decoded.
+    pub fn from_decoded(decoded: Decoded) -> Option<LoadedReference> {
+        let Decoded { frames, warning } = decoded;
+        let first = frames.first()?;
+        let (width, height) = first.image.dimensions();
+        let bytes = frames.iter().map(|frame| frame.image.as_raw().len()).sum();
+        let frames = frames
+            .into_iter()
+            .map(|frame| LoadedFrame {
+                handle: Handle::from_rgba(
+                    frame.image.width(),
+                    frame.image.height(),
+                    frame.image.into_vec(),
+                ),
+                delay: frame.delay,
+            })
+            .collect();
+        Some(LoadedReference {
+            width,
+            height,
+            frames,
+            warning,
+            bytes,
+        })
+    }
+    pub fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+    pub fn frame(&self, playback: &Playback) -> &LoadedFrame {
+        &self.frames[playback.frame.min(self.frames.len() - 1)]
+    }
+}
+
+/// Playback state of an animated reference.
+#[derive(Debug, Clone, Copy)]
+pub struct Playback {
+    pub playing: bool,
+    pub frame: usize,
+    pub speed: f32,
+    /// Time the current frame is already shown.
+    elapsed: Duration,
+}
+impl Default for Playback {
+    fn default() -> Self {
+        Playback {
+            playing: true,
+            frame: 0,
+            speed: 1.,
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+impl Playback {
+    /// Advances the animation by `delta`, skipping frames if necessary.
+    pub fn advance(&mut self, delta: Duration, frames: &[LoadedFrame]) {
+        if !self.playing || frames.len() < 2 {
+            return;
+        }
+        self.elapsed += delta.mul_f32(self.speed);
+        loop {
+            let delay = frames[self.frame % frames.len()].delay;
+            if delay.is_zero() || self.elapsed < delay {
+                break;
+            }
+            self.elapsed -= delay;
+            self.frame = (self.frame + 1) % frames.len();
+        }
+    }
+    pub fn apply(&mut self, action: PlaybackAction, frame_count: usize) {
+        match action {
+            PlaybackAction::TogglePlay => self.playing = !self.playing,
+            PlaybackAction::Step(amount) => {
+                self.playing = false;
+                self.elapsed = Duration::ZERO;
+                if frame_count > 0 {
+                    let frame_count = frame_count as isize;
+                    self.frame = (self.frame as isize + amount).rem_euclid(frame_count) as usize;
+                }
+            }
+            PlaybackAction::Seek(frame) => {
+                self.playing = false;
+                self.elapsed = Duration::ZERO;
+                self.frame = frame.min(frame_count.saturating_sub(1));
+            }
+            PlaybackAction::SetSpeed(speed) => self.speed = speed.clamp(MIN_SPEED, MAX_SPEED),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PlaybackAction {
+    TogglePlay,
+    Step(isize),
+    /// Jumps to the given frame.
+    Seek(usize),
+    SetSpeed(f32),
+}
+/// The view in which an animation is played.
+#[derive(Debug, Clone, Copy)]
+pub enum PlaybackTarget {
+    FigureDrawing,
+    Preview,
+    Pane(Pane),
+}
+
+pub fn view(loaded: &LoadedReference, playback: &Playback) -> widget::Image {
+    widget::Image::new(loaded.frame(playback).handle.clone())
+}
+
+/// Text explaining [`LoadedReference::warning`], if there is one.
+pub fn warning<'a>(loaded: &LoadedReference) -> Option<Element<'a, Message>> {
+    let text = match loaded.warning? {
+        DecodeWarning::Downsampled { width, height } => {
+            fl!("downsampled", width = width, height = height)
+        }
+    };
+    Some(widget::text::caption(text).into())
+}
+
+/// Play/pause, frame stepping, scrubbing and speed control for `loaded`.
+pub fn controls<'a>(
+    loaded: &LoadedReference,
+    playback: &Playback,
+    target: PlaybackTarget,
+) -> Element<'a, Message> {
+    let play_label = if playback.playing {
+        fl!("pause")
+    } else {
+        fl!("play")
+    };
+    widget::row()
+        .push(
+            button(text("<").size(14))
+                .padding(3)
+                .on_press(Message::Playback(target, PlaybackAction::Step(-1))),
+        )
+        .push(
+            button(text(play_label).size(14))
+                .padding(3)
+                .on_press(Message::Playback(target, PlaybackAction::TogglePlay)),
+        )
+        .push(
+            button(text(">").size(14))
+                .padding(3)
+                .on_press(Message::Playback(target, PlaybackAction::Step(1))),
+        )
+        .push(
+            widget::slider(
+                0..=loaded.frames.len() as u32 - 1,
+                playback.frame.min(loaded.frames.len() - 1) as u32,
+                move |frame| Message::Playback(target, PlaybackAction::Seek(frame as usize)),
+            )
+            .width(Length::Fixed(200.)),
+        )
+        .push(text(format!(
+            "{} {}/{}",
+            fl!("frame"),
+            playback.frame.min(loaded.frames.len() - 1) + 1,
+            loaded.frames.len()
+        )))
+        .push(
+            widget::slider(MIN_SPEED..=MAX_SPEED, playback.speed, move |speed| {
+                Message::Playback(target, PlaybackAction::SetSpeed(speed))
+            })
+            .step(0.05)
+            .width(Length::Fixed(120.)),
+        )
+        .push(text(format!("{:.2}x", playback.speed)))
+        .spacing(8)
+        .align_y(Alignment::Center)
+        .into()
+}
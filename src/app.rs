@@ -1,27 +1,49 @@
 // SPDX-License-Identifier: {{LICENSE}}
 
+use crate::album::{Album, AlbumEdit, Albums};
+use crate::animation::{self, LoadedReference, Playback, PlaybackAction, PlaybackTarget};
+use crate::archive;
+use crate::browser::{self, BrowserState};
+use crate::collection::{CollectionEdit, SmartCollection};
 use crate::config::Config;
-use crate::figure_drawing::{self, FigureDrawingState};
-use crate::reference::{RefStore, Reference, SourceFolder};
+use crate::decode::{self, DecodeLimits};
+use crate::duplicates::{self, DuplicateGroups, SearchState};
+use crate::export::{self, ExportItem, ExportOptions, ExportReport, ExportSource};
+use crate::figure_drawing::{self, FigureDrawingState, SessionSource, MAX_FAILED_LOADS};
+use crate::index::SourceIndex;
+use crate::metadata::MetadataEdit;
+use crate::query::QueryTarget;
+use crate::reference::{
+    self, ContentRating, RefFormat, RefStore, Reference, RelocationReport, SourceFolder,
+};
 use crate::reference_board::{self, ReferenceBoard};
+use crate::scan::{self, HashUpdate, RunningScan, ScanQueue, ScanUpdate};
+use crate::scan_options::ScanOptionsEdit;
+use crate::similarity::{self, SimilarSearch};
+use crate::thumbnail;
+use crate::watch::{self, SourceChanges};
 use crate::{fl, view};
 use cosmic::app::{Core, Task};
 use cosmic::cosmic_config::{self, CosmicConfigEntry};
-use cosmic::iced::{event, keyboard, Alignment, Subscription};
+use cosmic::iced::widget::scrollable::{self, Viewport};
+use cosmic::iced::{event, keyboard, task, Alignment, Subscription};
 use cosmic::iced_core::Event;
 use cosmic::iced_futures::MaybeSend;
 use cosmic::widget::pane_grid::{self, Axis, Pane};
 use cosmic::widget::{self, icon, menu, nav_bar};
 use cosmic::{cosmic_theme, theme, Application, ApplicationExt, Element};
 use futures_util::SinkExt;
-use image::RgbaImage;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
-use std::path::PathBuf;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tracing::info;
 
 const REPOSITORY: &str = "https://github.com/emiongit/refline";
+/// How often offline sources are checked for whether they are available again.
+const OFFLINE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Edits are saved at most this often, see [`Unsaved`].
+const SAVE_DELAY: Duration = Duration::from_secs(1);
 const APP_ICON: &[u8] = include_bytes!("../res/icons/hicolor/scalable/apps/icon.svg");
 
 /// The application model stores app-specific state used to describe its interface and
@@ -36,11 +58,43 @@ pub struct AppModel {
     /// Key bindings for the application's menu bar.
     key_binds: HashMap<menu::KeyBind, MenuAction>,
     // Configuration data that persists between application runs.
-    config: Config,
+    pub config: Config,
+    /// Handle to write changes of [`Self::config`] back.
+    config_handler: Option<cosmic_config::Config>,
+    /// Problem that occurred in the background and is shown until dismissed.
+    pub warning: Option<String>,
+    /// Text of the tag input of the metadata editor.
+    pub tag_draft: String,
+    /// Thumbnails of references that were recently requested by the ui.
+    pub thumbnails: Thumbnails,
+    /// Stores with typed edits that are not saved yet.
+    unsaved: Unsaved,
     /// Image references
     pub ref_store: RefStore,
+    pub albums: Albums,
+    pub export_source: ExportSource,
+    pub export_options: ExportOptions,
+    /// Progress or result of the last export.
+    pub export_status: Option<String>,
+    pub duplicate_search: SearchState,
+    /// State of computing the content hashes of new files.
+    pub hashing: SearchState,
+    /// Aborts computing the content hashes.
+    hashing_handle: Option<task::Handle>,
+    /// Index of the duplicate group shown side by side on the reference store page.
+    pub compared_duplicates: Option<usize>,
+    pub similar: SimilarSearch,
+    /// Aborts the running search for similar references.
+    similar_handle: Option<task::Handle>,
+    /// Source folders that are scanned in the background.
+    pub scan: ScanQueue,
+    /// Whether stale thumbnails were removed, which happens once after the first scans finished.
+    thumbnails_pruned: bool,
+    /// Result of checking the last relocated source folder.
+    pub relocation: Option<RelocationReport>,
     pub figure_drawing_state: FigureDrawingState,
     pub reference_board: ReferenceBoard,
+    pub browser: BrowserState,
 }
 
 /// Messages emitted by the application and its widgets.
@@ -51,16 +105,102 @@ pub enum Message {
     ToggleContextPage(ContextPage),
     UpdateConfig(Config),
     AddFilesToRefStore,
+    /// Adds zip or cbz archives as sources, whose images are read without extracting them.
+    AddArchivesToRefStore,
     LoadNewReference(ReferenceLoad),
     IncreaseReferenceCounter {
         amount: isize,
     },
-    LoadedNewReference(PathBuf, RgbaImage),
+    LoadedNewReference(PathBuf, LoadedReference, ReferenceLoad),
+    /// Loading a reference failed with the given error.
+    ReferenceLoadFailed(PathBuf, ReferenceLoad, String),
+    DismissWarning,
+    SetMaxImageMegapixels(u32),
+    SetMaxDecodeMemory(u32),
+    /// `None` if no thumbnail could be created for the path.
+    ThumbnailLoaded(PathBuf, Option<widget::image::Handle>),
+    /// Stale thumbnails were removed from the disk cache.
+    ThumbnailsPruned(usize),
+    /// Starts searching for duplicates in the background.
+    FindDuplicates,
+    DuplicatesFound(DuplicateGroups),
+    /// Files below the source folders changed on disk.
+    SourcesChanged(SourceChanges),
+    Scan(ScanUpdate),
+    Hashing(HashUpdate),
+    /// Checks in the background whether offline sources are available again.
+    CheckOfflineSources,
+    /// The offline sources at the paths can be scanned again.
+    SourcesAvailable(Vec<PathBuf>),
+    /// Stops scanning and hashing, the sources keep the references of their last complete scan.
+    CancelScan,
+    CompareDuplicates(Option<usize>),
+    /// Searches references that look like the current one.
+    FindSimilarToCurrent,
+    FindSimilar(PathBuf),
+    /// The references that look most like the first path, most similar first.
+    SimilarFound(PathBuf, Vec<PathBuf>),
+    /// Replaces the panes of the reference board with the best results of the last search.
+    SimilarToBoard,
+    BrowserFilterSource(Option<PathBuf>),
+    BrowserScrolled(Viewport),
+    /// Selects the reference at the index of the browser and opens it in the preview.
+    BrowserSelect(usize),
+    BrowserMove {
+        dx: isize,
+        dy: isize,
+    },
+    BrowserOpenPreview,
+    BrowserClosePreview,
+    EditMetadata(PathBuf, MetadataEdit),
+    /// Sets the content rating override of all references shown in the browser.
+    BulkSetContentOverride(Option<ContentRating>),
+    /// Hides the current reference from all sessions, or restores it if it is hidden.
+    /// On the figure drawing page this also moves on to the next reference.
+    HideCurrent,
+    TagDraftChanged(String),
     RemoveSource(SourceFolder),
+    /// Asks for the new location of the source folder and moves it there.
+    RelocateSource(PathBuf),
+    SourceRelocated(RelocationReport),
+    DismissRelocation,
     /// Can be assumed to always be of variant Message::Keypress`
     Keypress(keyboard::Event),
-    SetSfwFilter(bool),
-    SetSfwSource(bool, PathBuf),
+    SetMaxRating(ContentRating),
+    SetQuery(QueryTarget, String),
+    /// Saves the query of the browser as new smart collection.
+    AddCollection,
+    EditCollection(u32, CollectionEdit),
+    /// Saves the stores with typed edits, see [`Unsaved`].
+    SaveEdits,
+    RemoveCollection(u32),
+    SetSessionSource(SessionSource),
+    /// Creates a new album and makes it the active one.
+    AddAlbum,
+    EditAlbum(u32, AlbumEdit),
+    RemoveAlbum(u32),
+    SetActiveAlbum(u32),
+    /// Adds the current reference to the active album, or removes it if it is already part of it.
+    ToggleInActiveAlbum,
+    /// Replaces the panes of the reference board with the references of the album.
+    AlbumToBoard(u32),
+    /// Opens the export settings for the given references.
+    OpenExport(ExportSource),
+    SetExportOptions(ExportOptions),
+    /// Asks for a target folder and exports into it.
+    Export,
+    Exported(Result<ExportReport, String>),
+    SetSourceRating(ContentRating, PathBuf),
+    SetSourceSequences(bool, PathBuf),
+    /// Changes the scan options of the source, which are used once they are applied.
+    EditScanOptions(PathBuf, ScanOptionsEdit),
+    /// Saves the scan options of the source and scans it again.
+    ApplyScanOptions(PathBuf),
+    SetSourceFormat {
+        path: PathBuf,
+        format: RefFormat,
+        enabled: bool,
+    },
     TogglePin(Pane),
     Close(Pane),
     Clicked,
@@ -72,6 +212,9 @@ pub enum Message {
     SplitFocused(Axis),
     CloseFocused,
     FocusAdjacent,
+    Playback(PlaybackTarget, PlaybackAction),
+    /// Advances all running animations by [`animation::TICK`].
+    AnimationTick,
 }
 
 /// Create a COSMIC application from the app model
@@ -111,15 +254,27 @@ impl Application for AppModel {
             .data::<Page>(Page::ReferenceBoard)
             .icon(icon::from_name("applications-system-symbolic"));
 
+        nav.insert()
+            .text(fl!("similar"))
+            .data::<Page>(Page::Similar)
+            .icon(icon::from_name("edit-find-symbolic"));
+
         nav.insert()
             .text(fl!("reference_store"))
             .data::<Page>(Page::ReferenceStore)
             .icon(icon::from_name("applications-games-symbolic"))
             .activate();
 
-        let mut ref_store = RefStore::try_load().unwrap_or_default();
-        ref_store.sync_with_source_folders();
+        let ref_store = RefStore::try_load().unwrap_or_default();
+        for collection in &ref_store.collections {
+            insert_collection_nav(&mut nav, collection);
+        }
+        let albums = Albums::try_load().unwrap_or_default();
+        for album in &albums.albums {
+            insert_album_nav(&mut nav, album);
+        }
         let reference_board = ReferenceBoard::default();
+        let config_handler = cosmic_config::Config::new(Self::APP_ID, Config::VERSION).ok();
         // Construct the app model with the runtime's core.
         let mut app = AppModel {
             core,
@@ -128,8 +283,9 @@ impl Application for AppModel {
             reference_board,
             key_binds: HashMap::new(),
             // Optional configuration file for an application.
-            config: cosmic_config::Config::new(Self::APP_ID, Config::VERSION)
-                .map(|context| match Config::get_entry(&context) {
+            config: config_handler
+                .as_ref()
+                .map(|context| match Config::get_entry(context) {
                     Ok(config) => config,
                     Err((errors, config)) => {
                         for why in errors {
@@ -140,12 +296,38 @@ impl Application for AppModel {
                     }
                 })
                 .unwrap_or_default(),
+            config_handler,
+            warning: None,
+            tag_draft: String::new(),
+            thumbnails: Thumbnails::default(),
+            unsaved: Unsaved::default(),
+            browser: BrowserState::default(),
+            albums,
+            export_source: ExportSource::default(),
+            export_options: ExportOptions::default(),
+            export_status: None,
+            duplicate_search: SearchState::default(),
+            hashing: SearchState::default(),
+            hashing_handle: None,
+            thumbnails_pruned: false,
+            compared_duplicates: None,
+            similar: SimilarSearch::default(),
+            similar_handle: None,
+            scan: ScanQueue::default(),
+            relocation: None,
             ref_store,
             figure_drawing_state: FigureDrawingState::default(),
         };
 
         // Create a startup command that sets the window title.
-        let command = app.update_title();
+        // The sources show their indexed references until the scan finished.
+        let sources: Vec<PathBuf> = app
+            .ref_store
+            .source_folders
+            .iter()
+            .map(|source| source.path.clone())
+            .collect();
+        let command = Task::batch([app.update_title(), app.scan_sources(sources)]);
 
         (app, command)
     }
@@ -156,7 +338,10 @@ impl Application for AppModel {
             menu::root(fl!("view")),
             menu::items(
                 &self.key_binds,
-                vec![menu::Item::Button(fl!("about"), MenuAction::About)],
+                vec![
+                    menu::Item::Button(fl!("settings"), MenuAction::Settings),
+                    menu::Item::Button(fl!("about"), MenuAction::About),
+                ],
             ),
         )]);
 
@@ -176,6 +361,8 @@ impl Application for AppModel {
 
         Some(match self.context_page {
             ContextPage::About => self.about(),
+            ContextPage::Settings => view::settings(self),
+            ContextPage::Export => view::export(self),
         })
     }
 
@@ -192,6 +379,9 @@ impl Application for AppModel {
             Page::FigureDrawing => view::figure_drawing(self),
             Page::ReferenceBoard => view::reference_board(self),
             Page::ReferenceStore => view::reference_store(self),
+            Page::Collection(id) => view::collection(self, *id),
+            Page::Album(id) => view::album(self, *id),
+            Page::Similar => view::similar(self),
         }
     }
 
@@ -203,16 +393,52 @@ impl Application for AppModel {
     fn subscription(&self) -> Subscription<Self::Message> {
         struct MySubscription;
         // .map(|f| Message::NewEvent(f));
+        let animation_tick = if self.is_animation_playing() {
+            cosmic::iced::time::every(animation::TICK).map(|_| Message::AnimationTick)
+        } else {
+            Subscription::none()
+        };
+        let offline_check = if self
+            .ref_store
+            .source_folders
+            .iter()
+            .any(|source| source.offline)
+        {
+            cosmic::iced::time::every(OFFLINE_CHECK_INTERVAL).map(|_| Message::CheckOfflineSources)
+        } else {
+            Subscription::none()
+        };
+        let save_edits = if self.unsaved.any() {
+            cosmic::iced::time::every(SAVE_DELAY).map(|_| Message::SaveEdits)
+        } else {
+            Subscription::none()
+        };
         Subscription::batch(vec![
-            event::listen_with(|ev, _status, _id| {
+            animation_tick,
+            save_edits,
+            event::listen_with(|ev, status, _id| {
                 let Event::Keyboard(ev) = ev else {
                     return None;
                 };
+                // key presses handled by a widget, like typing into a text input, are no shortcuts
+                if status == event::Status::Captured {
+                    return None;
+                }
                 if matches!(ev, keyboard::Event::KeyPressed { .. }) {
                     return Some(Message::Keypress(ev));
                 }
                 None
             }),
+            offline_check,
+            // offline sources are watched again once they are available
+            watch::subscription(
+                self.ref_store
+                    .source_folders
+                    .iter()
+                    .filter(|source| !source.offline)
+                    .map(|source| source.path.clone())
+                    .collect(),
+            ),
             // Create a subscription which emits updates through a channel.
             Subscription::run_with_id(
                 std::any::TypeId::of::<MySubscription>(),
@@ -269,7 +495,17 @@ impl Application for AppModel {
             Message::AddFilesToRefStore => {
                 let folders = rfd::FileDialog::new().pick_folders();
                 if let Some(files) = folders {
-                    self.ref_store.push_folders(&files, true)
+                    let added = self.ref_store.push_folders(&files, ContentRating::General);
+                    return self.scan_sources(added);
+                }
+            }
+            Message::AddArchivesToRefStore => {
+                let archives = rfd::FileDialog::new()
+                    .add_filter(fl!("archives"), &archive::EXTENSIONS)
+                    .pick_files();
+                if let Some(files) = archives {
+                    let added = self.ref_store.push_folders(&files, ContentRating::General);
+                    return self.scan_sources(added);
                 }
             }
             Message::LoadNewReference(reference_load) => {
@@ -285,42 +521,92 @@ impl Application for AppModel {
                     if self.ref_store.ref_data.contains_key(&reference.path) {
                         return Task::none();
                     }
-                    return Task::future(load_reference(reference.clone(), reference_load));
+                    return Task::future(load_reference(
+                        reference.clone(),
+                        reference_load,
+                        self.config.decode_limits(),
+                    ));
                 }
-                let sfw_filter = self.figure_drawing_state.sfw_only;
-                let count = self.ref_store.reference_count(sfw_filter);
-                if count == 0 {
-                    tracing::error!("Can not load new reference as no folders were added");
+                if !reference_load.is_sampled() {
+                    tracing::error!(
+                        "{reference_load:?} references are loaded by path and never sampled"
+                    );
                     return Task::none();
                 }
-                let index = fastrand::usize(..count);
-                let reference = self
-                    .ref_store
-                    .get_reference(index, sfw_filter)
-                    .cloned()
-                    .expect("reference count was calculated wrong");
+                let max_rating = self.figure_drawing_state.max_rating;
+                let query = self.figure_drawing_state.query.query.clone();
+                let query = match self.figure_drawing_state.source {
+                    SessionSource::All | SessionSource::Album(_) | SessionSource::Similar => query,
+                    SessionSource::Collection(id) => {
+                        match self.ref_store.collection(id).map(SmartCollection::query) {
+                            Some(Ok(collection_query)) => collection_query.and(query),
+                            Some(Err(e)) => {
+                                tracing::error!("Can not sample from collection {id}: {e}");
+                                return Task::none();
+                            }
+                            None => {
+                                tracing::error!("Session source collection {id} does not exist");
+                                return Task::none();
+                            }
+                        }
+                    }
+                };
+                let query = &query;
+                // sources with a fixed list of references instead of the whole store
+                let paths = match self.figure_drawing_state.source {
+                    SessionSource::Album(id) => {
+                        let Some(album) = self.albums.get(id) else {
+                            tracing::error!("Session source album {id} does not exist");
+                            return Task::none();
+                        };
+                        Some(album.references.as_slice())
+                    }
+                    SessionSource::Similar => Some(self.similar.results.as_slice()),
+                    SessionSource::All | SessionSource::Collection(_) => None,
+                };
+                let failed = &self.figure_drawing_state.failed;
+                let candidates: Vec<&Reference> = match paths {
+                    Some(paths) => self
+                        .ref_store
+                        .filtered_paths(paths, max_rating, query)
+                        .filter(|reference| !failed.contains(&reference.path))
+                        .collect(),
+                    None => self
+                        .ref_store
+                        .filtered(max_rating, query)
+                        .filter(|reference| !failed.contains(&reference.path))
+                        .collect(),
+                };
+                if candidates.is_empty() {
+                    tracing::error!(
+                        "Can not load new reference as no reference matches the filters"
+                    );
+                    return Task::none();
+                }
+                let reference = candidates[fastrand::usize(..candidates.len())].clone();
                 self.figure_drawing_state.history.push(reference.clone());
                 if !self.figure_drawing_state.history.is_empty()
                     && self.figure_drawing_state.current_ref.is_none()
                 {
                     self.figure_drawing_state.current_ref = Some(0);
                 }
-                return Task::future(load_reference(reference, reference_load));
+                return Task::future(load_reference(
+                    reference,
+                    reference_load,
+                    self.config.decode_limits(),
+                ));
             }
-            Message::LoadedNewReference(path, img) => {
+            Message::LoadedNewReference(path, img, reference_load) => {
                 tracing::info!("Inserted new reference {path:?}");
                 self.ref_store.ref_data.insert(path, img);
+                self.evict_ref_data();
+                if !reference_load.is_sampled() {
+                    return Task::none();
+                }
+                self.figure_drawing_state.failed_in_row = 0;
                 let Some(index) = self.figure_drawing_state.current_ref else {
                     return Task::none();
                 };
-                if index != 0 {
-                    let keep = &self.figure_drawing_state.history[index.saturating_sub(3)..];
-                    self.ref_store.ref_data.retain(|k, _| {
-                        keep.contains(&crate::reference::Reference {
-                            path: k.to_path_buf(),
-                        })
-                    })
-                }
                 // tracing::warn!(
                 //     "prefetch condition indeex: {} history_len {}",
                 //     index,
@@ -347,15 +633,269 @@ impl Application for AppModel {
                     let message = match active_page {
                         Page::FigureDrawing => figure_drawing::keypress(key),
                         Page::ReferenceBoard => reference_board::keypress(key),
-                        Page::ReferenceStore => None,
+                        Page::ReferenceStore | Page::Collection(_) => browser::keypress(key),
+                        Page::Album(_) | Page::Similar => None,
                     };
                     if let Some(message) = message {
                         return Task::done(message.into());
                     }
                 }
             }
+            Message::ReferenceLoadFailed(path, reference_load, error) => {
+                tracing::warn!("Failed to load reference {path:?} with error {error}");
+                self.warning = Some(fl!(
+                    "load_failed",
+                    path = path.display().to_string(),
+                    error = error
+                ));
+                if !reference_load.is_sampled() {
+                    return Task::none();
+                }
+                let index = match reference_load {
+                    ReferenceLoad::Index(index) => Some(index),
+                    _ => None,
+                };
+                let state = &mut self.figure_drawing_state;
+                if !state.remove_failed(&path, index) {
+                    tracing::error!("Stopped loading references after {MAX_FAILED_LOADS} failures");
+                    return Task::none();
+                }
+                // the failed reference is gone, so the current one or a new one is loaded instead
+                let current = state.current_ref.and_then(|index| {
+                    let reference = state.history.get(index)?;
+                    Some((index, reference))
+                });
+                let next = match current {
+                    Some((index, reference))
+                        if !self.ref_store.ref_data.contains_key(&reference.path) =>
+                    {
+                        ReferenceLoad::Index(index)
+                    }
+                    Some((index, _)) if index + 1 < state.history.len() => return Task::none(),
+                    _ => ReferenceLoad::PushLast,
+                };
+                return Task::done(Message::LoadNewReference(next).into());
+            }
+            Message::DismissWarning => {
+                self.warning = None;
+            }
+            Message::ThumbnailLoaded(path, handle) => {
+                let thumbnail = match handle {
+                    Some(handle) => Thumbnail::Loaded(handle),
+                    None => Thumbnail::Failed,
+                };
+                self.thumbnails.insert(path, thumbnail);
+                // the grid size is only known after it was rendered,
+                // so further visible thumbnails are requested as soon as the first ones arrive
+                if matches!(
+                    self.nav.active_data::<Page>(),
+                    Some(Page::ReferenceStore | Page::Collection(_))
+                ) {
+                    return self.request_visible_thumbnails();
+                }
+            }
+            Message::ThumbnailsPruned(count) => {
+                info!("Removed {count} stale thumbnails from the cache");
+            }
+            Message::SourcesChanged(changes) => {
+                let mut albums_changed = false;
+                for (from, to) in &changes.renamed {
+                    albums_changed |= self.albums.rename(from, to, &[]);
+                }
+                if albums_changed {
+                    self.albums.save_to_disk();
+                }
+                let changed = self.ref_store.apply_changes(&changes);
+                if !changes.renamed.is_empty() {
+                    self.ref_store.save_to_disk();
+                }
+                if changed.is_empty() {
+                    return Task::none();
+                }
+                info!(
+                    "Scanning {} sources again after {} paths changed",
+                    changed.len(),
+                    changes.paths.len()
+                );
+                // saved with the stale directories, so they are listed again if the scan is cancelled
+                self.ref_store.save_index();
+                // modified files need a new thumbnail
+                self.thumbnails.retain(|path| {
+                    !changes
+                        .paths
+                        .iter()
+                        .any(|changed| path.starts_with(changed))
+                });
+                return self.scan_sources(changed);
+            }
+            Message::Scan(ScanUpdate::Progress { files, directory }) => {
+                if let Some(scan) = &mut self.scan.running {
+                    scan.files = files;
+                    scan.directory = Some(directory);
+                }
+            }
+            Message::Scan(ScanUpdate::Finished { path, index }) => {
+                // a cancelled scan may still have finished
+                if !self.scan.finish(&path) {
+                    return Task::none();
+                }
+                if let Some(source) = self
+                    .ref_store
+                    .source_folders
+                    .iter_mut()
+                    .find(|s| s.path == path)
+                {
+                    source.set_index(index);
+                    info!(
+                        "Scanned source {path:?} with {} references",
+                        source.children.len()
+                    );
+                    self.ref_store.save_index();
+                }
+                return self.continue_scanning();
+            }
+            Message::Scan(ScanUpdate::Offline { path }) => {
+                if !self.scan.finish(&path) {
+                    return Task::none();
+                }
+                if let Some(source) = self
+                    .ref_store
+                    .source_folders
+                    .iter_mut()
+                    .find(|s| s.path == path)
+                {
+                    tracing::warn!("Source {path:?} is offline, keeping its last scan");
+                    source.offline = true;
+                }
+                return self.continue_scanning();
+            }
+            Message::Hashing(HashUpdate::Hashed(hashed)) => {
+                for source in &mut self.ref_store.source_folders {
+                    if source.index.insert_hashes(&hashed) {
+                        source.update_children();
+                    }
+                }
+                self.ref_store.save_index();
+            }
+            Message::Hashing(HashUpdate::Finished) => {
+                // hashing was cancelled in the meantime
+                if self.hashing == SearchState::Idle {
+                    return Task::none();
+                }
+                let outdated = self.hashing == SearchState::Outdated;
+                self.hashing = SearchState::Idle;
+                self.hashing_handle = None;
+                // files moved into a source that is not scanned yet are found by the next pass
+                let complete = !outdated && self.scan.running.is_none();
+                let moved = self.ref_store.reconnect_moved(complete);
+                if !moved.is_empty() {
+                    info!(
+                        "Reconnected {} moved references by their content",
+                        moved.len()
+                    );
+                    let mut albums_changed = false;
+                    for (from, to) in &moved {
+                        albums_changed |= self.albums.rename(from, to, &[]);
+                    }
+                    if albums_changed {
+                        self.albums.save_to_disk();
+                    }
+                    self.ref_store.save_to_disk();
+                }
+                self.ref_store.save_index();
+                if outdated {
+                    return self.hash_contents();
+                }
+            }
+            Message::CheckOfflineSources => {
+                let offline: Vec<(PathBuf, SourceIndex)> = self
+                    .ref_store
+                    .source_folders
+                    .iter()
+                    .filter(|source| source.offline && !self.scan.contains(&source.path))
+                    .map(|source| (source.path.clone(), source.index.clone()))
+                    .collect();
+                if offline.is_empty() {
+                    return Task::none();
+                }
+                return Task::future(async move {
+                    let available = tokio::task::spawn_blocking(move || {
+                        offline
+                            .into_iter()
+                            .filter(|(path, index)| index.is_available(path))
+                            .map(|(path, _)| path)
+                            .collect()
+                    })
+                    .await
+                    .unwrap_or_default();
+                    Message::SourcesAvailable(available).into()
+                });
+            }
+            Message::SourcesAvailable(paths) => {
+                if paths.is_empty() {
+                    return Task::none();
+                }
+                info!("Sources {paths:?} are available again");
+                let paths: Vec<PathBuf> = paths
+                    .into_iter()
+                    .filter(|path| !self.scan.contains(path))
+                    .collect();
+                return self.scan_sources(paths);
+            }
+            Message::CancelScan => {
+                info!("Cancelled scanning the source folders");
+                self.scan.cancel_all();
+                if let Some(handle) = self.hashing_handle.take() {
+                    handle.abort();
+                }
+                self.hashing = SearchState::Idle;
+                return self.find_duplicates();
+            }
+            Message::FindDuplicates => return self.find_duplicates(),
+            Message::DuplicatesFound(groups) => {
+                info!("Found {} groups of duplicates", groups.groups.len());
+                let outdated = self.duplicate_search == SearchState::Outdated;
+                self.duplicate_search = SearchState::Idle;
+                self.ref_store.duplicates = groups;
+                if self
+                    .compared_duplicates
+                    .is_some_and(|index| self.ref_store.duplicates.get(index).is_none())
+                {
+                    self.compared_duplicates = None;
+                }
+                if outdated {
+                    return self.find_duplicates();
+                }
+            }
+            Message::CompareDuplicates(index) => {
+                self.compared_duplicates = index;
+                let paths = index
+                    .and_then(|index| self.ref_store.duplicates.get(index))
+                    .map(<[PathBuf]>::to_vec)
+                    .unwrap_or_default();
+                return self.request_thumbnails(paths);
+            }
+            Message::SetMaxImageMegapixels(megapixels) => {
+                if let Some(handler) = &self.config_handler {
+                    if let Err(why) = self.config.set_max_image_megapixels(handler, megapixels) {
+                        tracing::error!(%why, "failed to save max image megapixels");
+                    }
+                } else {
+                    self.config.max_image_megapixels = megapixels;
+                }
+            }
+            Message::SetMaxDecodeMemory(mib) => {
+                if let Some(handler) = &self.config_handler {
+                    if let Err(why) = self.config.set_max_decode_memory_mib(handler, mib) {
+                        tracing::error!(%why, "failed to save max decode memory");
+                    }
+                } else {
+                    self.config.max_decode_memory_mib = mib;
+                }
+            }
             Message::IncreaseReferenceCounter { amount } => {
                 let state = &mut self.figure_drawing_state;
+                state.playback = Playback::default();
                 state.current_ref = match state.current_ref {
                     Some(current) => Some(current.saturating_add_signed(amount)),
                     None => Some((amount - 1).min(0) as usize),
@@ -373,30 +913,435 @@ impl Application for AppModel {
                 };
             }
             Message::RemoveSource(source) => {
-                let Some(index) = self.ref_store.source_folders.iter().position(|s| s == s) else {
+                let Some(index) = self
+                    .ref_store
+                    .source_folders
+                    .iter()
+                    .position(|s| s == &source)
+                else {
                     tracing::warn!("Tried to remove source {source:?}, but it was not found");
                     return Task::none();
                 };
                 self.ref_store.source_folders.remove(index);
                 self.ref_store.save_to_disk();
+                self.ref_store.save_index();
+                if self.browser.source_filter.as_ref() == Some(&source.path) {
+                    self.browser.source_filter = None;
+                }
+                return Task::batch([self.refresh_browser(), self.find_duplicates()]);
             }
-            Message::SetSfwFilter(sfw_only) => {
-                self.figure_drawing_state.sfw_only = sfw_only;
-                info!("Set sfw filter to {sfw_only}");
+            Message::RelocateSource(from) => {
+                let mut dialog = rfd::FileDialog::new();
+                if let Some(parent) = from.parent() {
+                    dialog = dialog.set_directory(parent);
+                }
+                // a moved archive is picked as file
+                let to = if archive::is_archive(&from) {
+                    dialog
+                        .add_filter(fl!("archives"), &archive::EXTENSIONS)
+                        .pick_file()
+                } else {
+                    dialog.pick_folder()
+                };
+                let Some(to) = to else {
+                    return Task::none();
+                };
+                if to == from {
+                    return Task::none();
+                }
+                if self.ref_store.source_folders.iter().any(|s| s.path == to) {
+                    self.warning = Some(fl!("already_a_source", path = to.display().to_string()));
+                    return Task::none();
+                }
+                let nested = self.ref_store.nested_sources(&from);
+                if !self.ref_store.relocate_source(&from, &to) {
+                    tracing::warn!("Tried to relocate source {from:?}, but it was not found");
+                    return Task::none();
+                }
+                info!("Relocated source {from:?} to {to:?}");
+                if self.albums.rename(&from, &to, &nested) {
+                    self.albums.save_to_disk();
+                }
+                if self.browser.source_filter.as_ref() == Some(&from) {
+                    self.browser.source_filter = Some(to.clone());
+                }
+                self.ref_store.save_to_disk();
+                self.ref_store.save_index();
+                self.thumbnails.retain(|path| {
+                    !path.starts_with(&from) || nested.iter().any(|source| path.starts_with(source))
+                });
+                self.relocation = None;
+                let Some(source) = self.ref_store.source_folders.iter().find(|s| s.path == to)
+                else {
+                    return Task::none();
+                };
+                let index = source.index.clone();
+                let verify_path = to.clone();
+                let verify = Task::future(async move {
+                    let report = tokio::task::spawn_blocking(move || index.verify(&verify_path))
+                        .await
+                        .unwrap_or_default();
+                    Message::SourceRelocated(report).into()
+                });
+                return Task::batch([verify, self.scan_sources([to]), self.refresh_browser()]);
             }
-            Message::SetSfwSource(is_sfw, path) => {
-                if let Some(source) = self
+            Message::SourceRelocated(report) => {
+                info!(
+                    "Checked relocated source {:?}: {} files, {} missing, {} changed",
+                    report.source,
+                    report.checked,
+                    report.missing.len(),
+                    report.changed.len()
+                );
+                self.relocation = Some(report);
+            }
+            Message::DismissRelocation => self.relocation = None,
+            Message::SetMaxRating(max_rating) => {
+                self.figure_drawing_state.max_rating = max_rating;
+                info!("Set maximum content rating to {max_rating:?}");
+            }
+            Message::SetQuery(target, text) => match target {
+                QueryTarget::FigureDrawing => self.figure_drawing_state.query.set_text(text),
+                QueryTarget::Browser => {
+                    self.browser.query.set_text(text);
+                    return self.refresh_browser();
+                }
+            },
+            Message::AddCollection => {
+                let name = fl!(
+                    "new_collection",
+                    number = self.ref_store.collections.len() + 1
+                );
+                let query = self.browser.query.text.clone();
+                let id = self.ref_store.add_collection(name, query);
+                if let Some(collection) = self.ref_store.collection(id) {
+                    let nav_id = insert_collection_nav(&mut self.nav, collection);
+                    return self.on_nav_select(nav_id);
+                }
+            }
+            Message::EditCollection(id, edit) => {
+                let Some(collection) = self.ref_store.collection_mut(id) else {
+                    tracing::warn!("Tried to edit collection {id}, but it does not exist");
+                    return Task::none();
+                };
+                let typed = matches!(
+                    edit,
+                    CollectionEdit::Rename(_) | CollectionEdit::SetQuery(_)
+                );
+                collection.apply(edit);
+                let name = collection.name.clone();
+                if typed {
+                    self.unsaved.ref_store = true;
+                } else {
+                    self.ref_store.save_to_disk();
+                }
+                if let Some(nav_id) = self.collection_nav_id(id) {
+                    self.nav.text_set(nav_id, name);
+                }
+                return self.update_title().chain(self.refresh_browser());
+            }
+            Message::RemoveCollection(id) => {
+                self.ref_store.remove_collection(id);
+                if self.figure_drawing_state.source == SessionSource::Collection(id) {
+                    self.figure_drawing_state.source = SessionSource::All;
+                }
+                if let Some(nav_id) = self.collection_nav_id(id) {
+                    self.nav.remove(nav_id);
+                }
+                if let Some(store_id) = self.page_nav_id(&Page::ReferenceStore) {
+                    return self.on_nav_select(store_id);
+                }
+            }
+            Message::AddAlbum => {
+                let name = fl!("new_album", number = self.albums.albums.len() + 1);
+                let id = self.albums.add(name);
+                if let Some(album) = self.albums.get(id) {
+                    insert_album_nav(&mut self.nav, album);
+                }
+            }
+            Message::EditAlbum(id, edit) => {
+                let Some(album) = self.albums.get_mut(id) else {
+                    tracing::warn!("Tried to edit album {id}, but it does not exist");
+                    return Task::none();
+                };
+                let typed = matches!(edit, AlbumEdit::Rename(_));
+                album.apply(edit);
+                let name = album.name.clone();
+                if typed {
+                    self.unsaved.albums = true;
+                } else {
+                    self.albums.save_to_disk();
+                }
+                if let Some(nav_id) = self.page_nav_id(&Page::Album(id)) {
+                    self.nav.text_set(nav_id, name);
+                }
+                return self.update_title();
+            }
+            Message::RemoveAlbum(id) => {
+                self.albums.remove(id);
+                if self.figure_drawing_state.source == SessionSource::Album(id) {
+                    self.figure_drawing_state.source = SessionSource::All;
+                }
+                if let Some(nav_id) = self.page_nav_id(&Page::Album(id)) {
+                    self.nav.remove(nav_id);
+                }
+                if let Some(store_id) = self.page_nav_id(&Page::ReferenceStore) {
+                    return self.on_nav_select(store_id);
+                }
+            }
+            Message::SetActiveAlbum(id) => {
+                self.albums.active = Some(id);
+                self.albums.save_to_disk();
+            }
+            Message::ToggleInActiveAlbum => {
+                let Some(path) = self.current_reference_path() else {
+                    return Task::none();
+                };
+                let Some(id) = self.albums.active else {
+                    self.warning = Some(fl!("no_active_album"));
+                    return Task::none();
+                };
+                let edit = match self.albums.get(id) {
+                    Some(album) if album.contains(&path) => AlbumEdit::Remove(path),
+                    _ => AlbumEdit::Add(path),
+                };
+                return Task::done(Message::EditAlbum(id, edit).into());
+            }
+            Message::AlbumToBoard(id) => {
+                let Some(board) = self
+                    .albums
+                    .get(id)
+                    .and_then(|album| ReferenceBoard::from_paths(&album.references))
+                else {
+                    tracing::warn!("Can not show album {id} on the reference board as it is empty");
+                    return Task::none();
+                };
+                self.reference_board = board;
+                self.evict_ref_data();
+                if let Some(board_id) = self.page_nav_id(&Page::ReferenceBoard) {
+                    return self.on_nav_select(board_id);
+                }
+            }
+            Message::FindSimilarToCurrent => {
+                if let Some(path) = self.current_reference_path() {
+                    return Task::done(Message::FindSimilar(path).into());
+                }
+            }
+            Message::FindSimilar(path) => {
+                info!("Searching references similar to {path:?}");
+                if let Some(handle) = self.similar_handle.take() {
+                    handle.abort();
+                }
+                self.similar = SimilarSearch {
+                    target: Some(path.clone()),
+                    results: Vec::new(),
+                    running: true,
+                };
+                let paths: Vec<PathBuf> = self
+                    .ref_store
+                    .source_folders
+                    .iter()
+                    .flat_map(|source| &source.children)
+                    .map(|reference| reference.path.clone())
+                    .collect();
+                let limits = self.config.decode_limits();
+                let (search, handle) = Task::future(async move {
+                    let results = tokio::task::spawn_blocking({
+                        let path = path.clone();
+                        move || similarity::rank(&path, &similarity::features_all(&paths, &limits))
+                    })
+                    .await
+                    .unwrap_or_default();
+                    Message::SimilarFound(path, results).into()
+                })
+                .abortable();
+                self.similar_handle = Some(handle);
+                let page = match self.page_nav_id(&Page::Similar) {
+                    Some(similar_id) if self.nav.active_data::<Page>() != Some(&Page::Similar) => {
+                        self.on_nav_select(similar_id)
+                    }
+                    _ => Task::none(),
+                };
+                return Task::batch([page, search]);
+            }
+            Message::SimilarFound(target, results) => {
+                // a newer search was started in the meantime
+                if self.similar.target.as_ref() != Some(&target) {
+                    return Task::none();
+                }
+                info!("Found {} references similar to {target:?}", results.len());
+                self.similar_handle = None;
+                self.similar.results = results;
+                self.similar.running = false;
+                let mut paths = self.similar.results.clone();
+                paths.push(target);
+                return self.request_thumbnails(paths);
+            }
+            Message::SimilarToBoard => {
+                let results = &self.similar.results;
+                if results.is_empty() {
+                    tracing::warn!(
+                        "Can not show similar references on the reference board as there are none"
+                    );
+                    return Task::none();
+                }
+                self.reference_board
+                    .append(&results[..results.len().min(similarity::MAX_BOARD_RESULTS)]);
+                self.evict_ref_data();
+                if let Some(board_id) = self.page_nav_id(&Page::ReferenceBoard) {
+                    return self.on_nav_select(board_id);
+                }
+            }
+            Message::OpenExport(source) => {
+                self.export_source = source;
+                self.export_status = None;
+                self.context_page = ContextPage::Export;
+                self.core.window.show_context = true;
+                self.set_context_title(ContextPage::Export.title());
+            }
+            Message::SetExportOptions(options) => {
+                self.export_options = options;
+            }
+            Message::Export => {
+                let paths: Vec<PathBuf> = match self.export_source {
+                    ExportSource::Browser => self
+                        .browser
+                        .entries
+                        .iter()
+                        .map(|reference| reference.path.clone())
+                        .collect(),
+                    ExportSource::Album(id) => self
+                        .albums
+                        .get(id)
+                        .map(|album| album.references.clone())
+                        .unwrap_or_default(),
+                };
+                let items = self.export_items(&paths);
+                if items.is_empty() {
+                    self.export_status = Some(fl!("nothing_to_export"));
+                    return Task::none();
+                }
+                let Some(target) = rfd::FileDialog::new().pick_folder() else {
+                    return Task::none();
+                };
+                let options = self.export_options;
+                self.export_status = Some(fl!("exporting"));
+                return Task::future(async move {
+                    let result = tokio::task::spawn_blocking(move || {
+                        export::export(&items, &target, options).map_err(|e| e.to_string())
+                    })
+                    .await
+                    .unwrap_or_else(|e| Err(e.to_string()));
+                    Message::Exported(result).into()
+                });
+            }
+            Message::Exported(result) => {
+                self.export_status = Some(match result {
+                    Ok(report) => fl!(
+                        "exported",
+                        exported = report.exported,
+                        failed = report.failed
+                    ),
+                    Err(error) => fl!("export_failed", error = error),
+                });
+            }
+            Message::SetSessionSource(source) => {
+                self.figure_drawing_state.source = source;
+                info!("Set session source to {source:?}");
+            }
+            Message::SetSourceRating(rating, path) => {
+                let Some(source) = self
                     .ref_store
                     .source_folders
                     .iter_mut()
                     .find(|s| s.path == path)
-                {
-                    source.is_sfw = is_sfw;
-                } else {
+                else {
+                    tracing::warn!(
+                        "Tried to set the content rating of source. No source registered at path {path:?}"
+                    );
+                    return Task::none();
+                };
+                source.rating = rating;
+                self.ref_store.save_to_disk();
+                return self.refresh_browser();
+            }
+            Message::SetSourceSequences(detect_sequences, path) => {
+                let Some(source) = self
+                    .ref_store
+                    .source_folders
+                    .iter_mut()
+                    .find(|s| s.path == path)
+                else {
+                    tracing::warn!(
+                        "Tried to toggle sequence detection for source. No source registered at path {path:?}"
+                    );
+                    return Task::none();
+                };
+                source.detect_sequences = detect_sequences;
+                source.update_children();
+                self.ref_store.save_to_disk();
+                return Task::batch([self.refresh_browser(), self.find_duplicates()]);
+            }
+            Message::EditScanOptions(path, edit) => {
+                let Some(source) = self
+                    .ref_store
+                    .source_folders
+                    .iter_mut()
+                    .find(|s| s.path == path)
+                else {
                     tracing::warn!(
-                        "Tried to toggle sfw flag for source. No source registered at path {path:?}"
+                        "Tried to edit the scan options of source. No source registered at path {path:?}"
                     );
+                    return Task::none();
+                };
+                source.scan_options.apply(edit);
+            }
+            Message::ApplyScanOptions(path) => {
+                let Some(source) = self
+                    .ref_store
+                    .source_folders
+                    .iter()
+                    .find(|s| s.path == path)
+                else {
+                    tracing::warn!(
+                        "Tried to apply the scan options of source. No source registered at path {path:?}"
+                    );
+                    return Task::none();
+                };
+                if let Err(e) = source.scan_options.filter() {
+                    self.warning = Some(e.to_string());
+                    return Task::none();
+                }
+                info!(
+                    "Scanning source {path:?} with options {:?}",
+                    source.scan_options
+                );
+                self.ref_store.save_to_disk();
+                return self.scan_sources([path]);
+            }
+            Message::SetSourceFormat {
+                path,
+                format,
+                enabled,
+            } => {
+                let Some(source) = self
+                    .ref_store
+                    .source_folders
+                    .iter_mut()
+                    .find(|s| s.path == path)
+                else {
+                    tracing::warn!(
+                        "Tried to set format {format:?} for source. No source registered at path {path:?}"
+                    );
+                    return Task::none();
+                };
+                if enabled {
+                    source.formats.insert(format);
+                } else {
+                    source.formats.remove(&format);
                 }
+                self.ref_store.save_to_disk();
+                return self.scan_sources([path]);
             }
             Message::TogglePin(_) => todo!(),
             Message::Close(_) => todo!(),
@@ -409,6 +1354,158 @@ impl Application for AppModel {
             Message::SplitFocused(_) => todo!(),
             Message::CloseFocused => todo!(),
             Message::FocusAdjacent => todo!(),
+            Message::Playback(target, action) => match target {
+                PlaybackTarget::FigureDrawing => {
+                    let state = &mut self.figure_drawing_state;
+                    let frame_count = state
+                        .current_ref
+                        .and_then(|index| state.history.get(index))
+                        .and_then(|reference| self.ref_store.ref_data.get(&reference.path))
+                        .map(|loaded| loaded.frames.len())
+                        .unwrap_or_default();
+                    state.playback.apply(action, frame_count);
+                }
+                PlaybackTarget::Preview => {
+                    let frame_count = self
+                        .browser
+                        .preview_reference()
+                        .and_then(|reference| self.ref_store.ref_data.get(&reference.path))
+                        .map(|loaded| loaded.frames.len())
+                        .unwrap_or_default();
+                    self.browser.playback.apply(action, frame_count);
+                }
+                PlaybackTarget::Pane(pane) => {
+                    let Some(node) = self.reference_board.panes.get_mut(pane) else {
+                        return Task::none();
+                    };
+                    let frame_count = self
+                        .ref_store
+                        .ref_data
+                        .get(&node.path)
+                        .map(|loaded| loaded.frames.len())
+                        .unwrap_or_default();
+                    node.playback.apply(action, frame_count);
+                }
+            },
+            Message::AnimationTick => {
+                let ref_data = &self.ref_store.ref_data;
+                let state = &mut self.figure_drawing_state;
+                if let Some(loaded) = state
+                    .current_ref
+                    .and_then(|index| state.history.get(index))
+                    .and_then(|reference| ref_data.get(&reference.path))
+                {
+                    state.playback.advance(animation::TICK, &loaded.frames);
+                }
+                for (_, node) in self.reference_board.panes.iter_mut() {
+                    if let Some(loaded) = ref_data.get(&node.path) {
+                        node.playback.advance(animation::TICK, &loaded.frames);
+                    }
+                }
+                let browser = &mut self.browser;
+                if let Some(loaded) = browser
+                    .preview_reference()
+                    .and_then(|reference| ref_data.get(&reference.path))
+                {
+                    browser.playback.advance(animation::TICK, &loaded.frames);
+                }
+            }
+            Message::BrowserFilterSource(source_filter) => {
+                self.browser.source_filter = source_filter;
+                self.browser.scroll_offset = 0.;
+                return self.refresh_browser().chain(scrollable::scroll_to(
+                    browser::scroll_id(),
+                    scrollable::AbsoluteOffset { x: 0., y: 0. },
+                ));
+            }
+            Message::BrowserScrolled(viewport) => {
+                self.browser.on_scroll(viewport);
+                return self.request_visible_thumbnails();
+            }
+            Message::BrowserSelect(index) => {
+                self.browser.selected = Some(index);
+                self.browser.preview = true;
+                self.browser.playback = Playback::default();
+                return self.load_preview();
+            }
+            Message::BrowserMove { dx, dy } => {
+                self.browser.move_selection(dx, dy);
+                if self.browser.preview {
+                    return self.load_preview();
+                }
+                if let Some(offset) = self.browser.scroll_to_selection() {
+                    return scrollable::scroll_to(browser::scroll_id(), offset);
+                }
+            }
+            Message::BrowserOpenPreview => {
+                if self.browser.selected.is_some() {
+                    self.browser.preview = true;
+                    return self.load_preview();
+                }
+            }
+            Message::EditMetadata(path, edit) => {
+                if matches!(edit, MetadataEdit::AddTag(_)) {
+                    self.tag_draft.clear();
+                }
+                self.ref_store.apply_metadata_edit(&path, edit);
+                self.unsaved.ref_store = true;
+            }
+            Message::SaveEdits => {
+                if std::mem::take(&mut self.unsaved.ref_store) {
+                    self.ref_store.save_to_disk();
+                }
+                if std::mem::take(&mut self.unsaved.albums) {
+                    self.albums.save_to_disk();
+                }
+            }
+            Message::BulkSetContentOverride(content_override) => {
+                info!(
+                    "Set content rating override of {} references to {content_override:?}",
+                    self.browser.entries.len()
+                );
+                self.ref_store.apply_metadata_edit_bulk(
+                    self.browser
+                        .entries
+                        .iter()
+                        .map(|reference| reference.path.as_path()),
+                    MetadataEdit::SetContentOverride(content_override),
+                );
+                self.unsaved.ref_store = true;
+                return self.refresh_browser();
+            }
+            Message::HideCurrent => {
+                let Some(path) = self.current_reference_path() else {
+                    return Task::none();
+                };
+                let hidden = !self.ref_store.is_hidden(&path);
+                info!("Set hidden of {path:?} to {hidden}");
+                self.ref_store
+                    .apply_metadata_edit(&path, MetadataEdit::SetHidden(hidden));
+                self.unsaved.ref_store = true;
+                let refresh = self.refresh_browser();
+                if hidden && self.nav.active_data::<Page>() == Some(&Page::FigureDrawing) {
+                    return Task::batch([
+                        refresh,
+                        Task::done(Message::IncreaseReferenceCounter { amount: 1 }.into()),
+                    ]);
+                }
+                return refresh;
+            }
+            Message::TagDraftChanged(tag_draft) => {
+                self.tag_draft = tag_draft;
+            }
+            Message::BrowserClosePreview => {
+                self.browser.preview = false;
+                self.evict_ref_data();
+                // the grid is created anew, so restore where the user was
+                return scrollable::scroll_to(
+                    browser::scroll_id(),
+                    scrollable::AbsoluteOffset {
+                        x: 0.,
+                        y: self.browser.scroll_offset,
+                    },
+                );
+            }
         }
         Task::none()
     }
@@ -418,11 +1515,21 @@ impl Application for AppModel {
         // Activate the page in the model.
         self.nav.activate(id);
         let on_enter_task: Task<Self::Message> =
-            if let Some(page) = self.nav.data::<Page>(self.nav.active()) {
+            if let Some(page) = self.nav.data::<Page>(self.nav.active()).cloned() {
                 match page {
                     Page::FigureDrawing => self.on_figure_drawing_enter(),
-                    Page::ReferenceBoard => Task::none(),
-                    Page::ReferenceStore => Task::none(),
+                    Page::ReferenceBoard => self.on_reference_board_enter(),
+                    Page::ReferenceStore => self.browse_collection(None),
+                    Page::Collection(id) => self.browse_collection(Some(id)),
+                    Page::Album(id) => {
+                        let paths = self
+                            .albums
+                            .get(id)
+                            .map(|album| album.references.clone())
+                            .unwrap_or_default();
+                        self.request_thumbnails(paths)
+                    }
+                    Page::Similar => Task::none(),
                 }
             } else {
                 Task::none()
@@ -444,6 +1551,360 @@ impl AppModel {
             Task::none()
         }
     }
+    /// Loads the references of all panes which are not decoded yet.
+    pub fn on_reference_board_enter(&self) -> Task<<AppModel as cosmic::Application>::Message> {
+        let tasks: Vec<_> = self
+            .reference_board
+            .panes
+            .iter()
+            .filter(|(_, node)| !self.ref_store.ref_data.contains_key(&node.path))
+            .map(|(_, node)| {
+                let reference = Reference {
+                    path: node.path.clone(),
+                    ..Default::default()
+                };
+                Task::future(load_reference(
+                    reference,
+                    ReferenceLoad::Board,
+                    self.config.decode_limits(),
+                ))
+            })
+            .collect();
+        Task::batch(tasks)
+    }
+    /// Shows the smart collection with `id` in the browser, or the whole store if `None`.
+    fn browse_collection(
+        &mut self,
+        id: Option<u32>,
+    ) -> Task<<AppModel as cosmic::Application>::Message> {
+        if self.browser.collection != id {
+            self.browser.collection = id;
+            self.browser.selected = None;
+            self.browser.preview = false;
+            self.browser.scroll_offset = 0.;
+        }
+        self.refresh_browser().chain(scrollable::scroll_to(
+            browser::scroll_id(),
+            scrollable::AbsoluteOffset {
+                x: 0.,
+                y: self.browser.scroll_offset,
+            },
+        ))
+    }
+    /// The files to export for the references at `paths`, keeping their order.
+    fn export_items(&self, paths: &[PathBuf]) -> Vec<ExportItem> {
+        let references: HashMap<&Path, (&SourceFolder, &Reference)> = self
+            .ref_store
+            .source_folders
+            .iter()
+            .flat_map(|source| {
+                source
+                    .children
+                    .iter()
+                    .map(move |reference| (reference.path.as_path(), (source, reference)))
+            })
+            .collect();
+        paths
+            .iter()
+            .filter_map(|path| references.get(path.as_path()))
+            .map(|(source, reference)| ExportItem {
+                source: source.path.clone(),
+                files: if reference.is_sequence() {
+                    reference.sequence.clone()
+                } else {
+                    vec![reference.path.clone()]
+                },
+            })
+            .collect()
+    }
+    /// Path of the reference the user is looking at on the active page.
+    fn current_reference_path(&self) -> Option<PathBuf> {
+        match self.nav.active_data::<Page>()? {
+            Page::FigureDrawing => {
+                let state = &self.figure_drawing_state;
+                state
+                    .current_ref
+                    .and_then(|index| state.history.get(index))
+                    .map(|reference| reference.path.clone())
+            }
+            Page::ReferenceStore | Page::Collection(_) => self
+                .browser
+                .selected_reference()
+                .map(|reference| reference.path.clone()),
+            Page::ReferenceBoard | Page::Album(_) | Page::Similar => None,
+        }
+    }
+    fn page_nav_id(&self, page: &Page) -> Option<nav_bar::Id> {
+        self.nav
+            .iter()
+            .find(|id| self.nav.data::<Page>(*id) == Some(page))
+    }
+    fn collection_nav_id(&self, id: u32) -> Option<nav_bar::Id> {
+        self.page_nav_id(&Page::Collection(id))
+    }
+    /// Updates the references shown in the browser after the store changed.
+    pub fn refresh_browser(&mut self) -> Task<<AppModel as cosmic::Application>::Message> {
+        self.browser.refresh(&self.ref_store);
+        self.request_visible_thumbnails()
+    }
+    fn request_visible_thumbnails(&mut self) -> Task<<AppModel as cosmic::Application>::Message> {
+        let paths: Vec<PathBuf> = self.browser.entries[self.browser.visible_range()]
+            .iter()
+            .map(|reference| reference.path.clone())
+            .collect();
+        self.request_thumbnails(paths)
+    }
+    /// Loads the reference shown in the browser preview.
+    fn load_preview(&mut self) -> Task<<AppModel as cosmic::Application>::Message> {
+        self.evict_ref_data();
+        let Some(reference) = self.browser.preview_reference() else {
+            return Task::none();
+        };
+        if self.ref_store.ref_data.contains_key(&reference.path) {
+            return Task::none();
+        }
+        Task::future(load_reference(
+            reference.clone(),
+            ReferenceLoad::Preview,
+            self.config.decode_limits(),
+        ))
+    }
+    /// Drops decoded references that are neither in the recent figure drawing history,
+    /// on the reference board nor in the browser preview.
+    ///
+    /// If the remaining ones exceed [`reference::IMAGE_CACHE_BUDGET`], the older history entries
+    /// are dropped first. The current reference is always kept.
+    fn evict_ref_data(&mut self) {
+        let history = &self.figure_drawing_state.history;
+        let (current, recent) = match self.figure_drawing_state.current_ref {
+            Some(index) => (
+                history.get(index),
+                &history[index.saturating_sub(3).min(history.len())..],
+            ),
+            None => (None, &history[..]),
+        };
+        let board = self
+            .reference_board
+            .panes
+            .iter()
+            .map(|(_, node)| &node.path);
+        let preview = self.browser.preview_reference().map(|r| &r.path);
+        // ordered by how much the references are needed
+        let wanted: Vec<&PathBuf> = current
+            .map(|reference| &reference.path)
+            .into_iter()
+            .chain(preview)
+            .chain(board)
+            .chain(recent.iter().rev().map(|reference| &reference.path))
+            .collect();
+        let mut keep = HashSet::new();
+        let mut bytes = 0;
+        for path in wanted {
+            let Some(loaded) = self.ref_store.ref_data.get(path) else {
+                continue;
+            };
+            if keep.contains(path) {
+                continue;
+            }
+            if !keep.is_empty() && bytes + loaded.bytes > reference::IMAGE_CACHE_BUDGET {
+                continue;
+            }
+            bytes += loaded.bytes;
+            keep.insert(path.clone());
+        }
+        self.ref_store
+            .ref_data
+            .retain(|path, _| keep.contains(path));
+    }
+    /// Starts loading the thumbnails of `paths` which are neither loaded nor loading.
+    /// All of them count as recently used, so they are kept over thumbnails out of view.
+    pub fn request_thumbnails(
+        &mut self,
+        paths: impl IntoIterator<Item = PathBuf>,
+    ) -> Task<<AppModel as cosmic::Application>::Message> {
+        let limits = self.config.decode_limits();
+        let tasks: Vec<_> = paths
+            .into_iter()
+            .filter_map(|path| {
+                // known thumbnails only become the most recently used ones
+                if self.thumbnails.touch(&path) {
+                    return None;
+                }
+                self.thumbnails.insert(path.clone(), Thumbnail::Loading);
+                Some(Task::future(load_thumbnail(path, limits)))
+            })
+            .collect();
+        Task::batch(tasks)
+    }
+    /// Removes thumbnails of files that changed or are no longer part of the store in the background.
+    /// Files of offline sources and files that can not be read right now keep the thumbnail of
+    /// their indexed state.
+    fn prune_thumbnails(&mut self) -> Task<<AppModel as cosmic::Application>::Message> {
+        self.thumbnails_pruned = true;
+        let files: Vec<(PathBuf, u64, u64, bool)> = self
+            .ref_store
+            .source_folders
+            .iter()
+            .flat_map(|source| {
+                source
+                    .index
+                    .file_states()
+                    .map(move |(path, len, modified)| {
+                        (path.to_path_buf(), len, modified, source.offline)
+                    })
+            })
+            .collect();
+        Task::future(async move {
+            let count = tokio::task::spawn_blocking(move || {
+                let size = thumbnail::THUMBNAIL_SIZE;
+                let valid: HashSet<String> = files
+                    .iter()
+                    .map(|(path, len, modified, offline)| {
+                        let current = if *offline {
+                            None
+                        } else {
+                            thumbnail::cache_key(path, size)
+                        };
+                        current
+                            .unwrap_or_else(|| thumbnail::indexed_key(path, size, *len, *modified))
+                    })
+                    .collect();
+                thumbnail::prune(&valid, size)
+            })
+            .await
+            .unwrap_or_default();
+            Message::ThumbnailsPruned(count).into()
+        })
+    }
+    /// Queues the source folders at `paths` for scanning and starts scanning if it is not running.
+    fn scan_sources(
+        &mut self,
+        paths: impl IntoIterator<Item = PathBuf>,
+    ) -> Task<<AppModel as cosmic::Application>::Message> {
+        self.scan.push(paths);
+        if self.scan.running.is_some() {
+            return Task::none();
+        }
+        self.start_next_scan()
+    }
+    /// Starts the next queued scan, or searches for duplicates once all sources are scanned.
+    /// Stale thumbnails are only removed then, as the store is incomplete during the first scans.
+    fn continue_scanning(&mut self) -> Task<<AppModel as cosmic::Application>::Message> {
+        let next = self.start_next_scan();
+        if self.scan.running.is_some() {
+            return Task::batch([self.refresh_browser(), next]);
+        }
+        let prune = if self.thumbnails_pruned {
+            Task::none()
+        } else {
+            self.prune_thumbnails()
+        };
+        Task::batch([
+            self.refresh_browser(),
+            self.find_duplicates(),
+            self.hash_contents(),
+            prune,
+        ])
+    }
+    /// Starts scanning the next queued source folder that still exists in the store.
+    fn start_next_scan(&mut self) -> Task<<AppModel as cosmic::Application>::Message> {
+        while let Some(path) = self.scan.pop() {
+            let Some(source) = self
+                .ref_store
+                .source_folders
+                .iter()
+                .find(|s| s.path == path)
+            else {
+                continue;
+            };
+            let (task, handle) = cosmic::iced::Task::stream(scan::scan(source))
+                .map(Into::into)
+                .abortable();
+            self.scan.running = Some(RunningScan::new(path, handle));
+            return task;
+        }
+        Task::none()
+    }
+    /// Computes the content hashes of new files in the background,
+    /// which then reconnect moved files to their metadata.
+    /// If hashing is already running, it is repeated once it finished.
+    fn hash_contents(&mut self) -> Task<<AppModel as cosmic::Application>::Message> {
+        if self.hashing != SearchState::Idle {
+            self.hashing = SearchState::Outdated;
+            return Task::none();
+        }
+        let mut seen = HashSet::new();
+        // files of nested sources are part of their parent source as well
+        let unhashed: Vec<(PathBuf, u64, u64)> = self
+            .ref_store
+            .source_folders
+            .iter()
+            .filter(|source| !source.offline)
+            .flat_map(|source| source.index.unhashed())
+            .filter(|(path, _, _)| seen.insert(*path))
+            .map(|(path, size, modified)| (path.to_path_buf(), size, modified))
+            .collect();
+        self.hashing = SearchState::Running;
+        let (task, handle) = cosmic::iced::Task::stream(scan::hash(unhashed))
+            .map(Into::into)
+            .abortable();
+        self.hashing_handle = Some(handle);
+        task
+    }
+    /// Groups the references of all source folders by their perceptual hash in the background.
+    /// If a search is already running, it is repeated once it finished.
+    fn find_duplicates(&mut self) -> Task<<AppModel as cosmic::Application>::Message> {
+        if self.duplicate_search != SearchState::Idle {
+            self.duplicate_search = SearchState::Outdated;
+            return Task::none();
+        }
+        self.duplicate_search = SearchState::Running;
+        let paths: Vec<PathBuf> = self
+            .ref_store
+            .source_folders
+            .iter()
+            .flat_map(|source| &source.children)
+            .map(|reference| reference.path.clone())
+            .collect();
+        let limits = self.config.decode_limits();
+        Task::future(async move {
+            let groups = tokio::task::spawn_blocking(move || {
+                DuplicateGroups::find(&duplicates::hash_all(&paths, &limits))
+            })
+            .await
+            .unwrap_or_default();
+            Message::DuplicatesFound(groups).into()
+        })
+    }
+    /// Whether any visible reference is an animation that is currently playing.
+    fn is_animation_playing(&self) -> bool {
+        let ref_data = &self.ref_store.ref_data;
+        let is_playing = |path: &PathBuf, playback: &Playback| {
+            playback.playing
+                && ref_data
+                    .get(path)
+                    .is_some_and(|loaded| loaded.is_animated())
+        };
+        match self.nav.active_data::<Page>() {
+            Some(Page::FigureDrawing) => {
+                let state = &self.figure_drawing_state;
+                state
+                    .current_ref
+                    .and_then(|index| state.history.get(index))
+                    .is_some_and(|reference| is_playing(&reference.path, &state.playback))
+            }
+            Some(Page::ReferenceBoard) => self
+                .reference_board
+                .panes
+                .iter()
+                .any(|(_, node)| is_playing(&node.path, &node.playback)),
+            Some(Page::ReferenceStore | Page::Collection(_)) => self
+                .browser
+                .preview_reference()
+                .is_some_and(|reference| is_playing(&reference.path, &self.browser.playback)),
+            _ => false,
+        }
+    }
     /// The about page for this app.
     pub fn about(&self) -> Element<Message> {
         let cosmic_theme::Spacing { space_xxs, .. } = theme::active().cosmic().spacing;
@@ -482,12 +1943,38 @@ impl AppModel {
     }
 }
 
+/// Adds a nav item for `collection` below the reference store.
+fn insert_collection_nav(nav: &mut nav_bar::Model, collection: &SmartCollection) -> nav_bar::Id {
+    nav.insert()
+        .text(collection.name.clone())
+        .data::<Page>(Page::Collection(collection.id))
+        .icon(icon::from_name("folder-saved-search-symbolic"))
+        .indent(1)
+        .id()
+}
+
+/// Adds a nav item for `album` below the reference store.
+fn insert_album_nav(nav: &mut nav_bar::Model, album: &Album) -> nav_bar::Id {
+    nav.insert()
+        .text(album.name.clone())
+        .data::<Page>(Page::Album(album.id))
+        .icon(icon::from_name("folder-pictures-symbolic"))
+        .indent(1)
+        .id()
+}
+
 /// The page to display in the application.
 #[derive(PartialEq, Debug, Clone)]
 pub enum Page {
     FigureDrawing,
     ReferenceBoard,
     ReferenceStore,
+    /// The smart collection with this id.
+    Collection(u32),
+    /// The album with this id.
+    Album(u32),
+    /// Results of the last "more like this" search.
+    Similar,
 }
 
 /// The context page to display in the context drawer.
@@ -495,12 +1982,16 @@ pub enum Page {
 pub enum ContextPage {
     #[default]
     About,
+    Settings,
+    Export,
 }
 
 impl ContextPage {
     fn title(&self) -> String {
         match self {
             Self::About => fl!("about"),
+            Self::Settings => fl!("settings"),
+            Self::Export => fl!("export"),
         }
     }
 }
@@ -508,6 +1999,7 @@ impl ContextPage {
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum MenuAction {
     About,
+    Settings,
 }
 
 impl menu::action::MenuAction for MenuAction {
@@ -516,26 +2008,128 @@ impl menu::action::MenuAction for MenuAction {
     fn message(&self) -> Self::Message {
         match self {
             MenuAction::About => Message::ToggleContextPage(ContextPage::About),
+            MenuAction::Settings => Message::ToggleContextPage(ContextPage::Settings),
         }
     }
 }
 fn load_reference<T: From<Message>>(
     reference: Reference,
     reference_load: ReferenceLoad,
+    limits: DecodeLimits,
 ) -> impl Future<Output = T> + MaybeSend + 'static {
     async move {
         info!("start loading image as reference");
-        let Ok(img) = image::open(&reference.path) else {
-            tracing::warn!("failed loading image");
-            return Message::LoadNewReference(reference_load).into();
+        let loaded = decode::decode_reference(&reference, reference::ANIMATION_BUDGET, &limits)
+            .map_err(|e| e.to_string())
+            .and_then(|decoded| {
+                LoadedReference::from_decoded(decoded).ok_or_else(|| String::from("no frames"))
+            });
+        let loaded = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                tracing::warn!("failed loading image");
+                return Message::ReferenceLoadFailed(reference.path, reference_load, e).into();
+            }
         };
-        let img = img.to_rgba8();
         info!("finish loading reference");
-        return Message::LoadedNewReference(reference.path, img).into();
+        return Message::LoadedNewReference(reference.path, loaded, reference_load).into();
+    }
+}
+fn load_thumbnail<T: From<Message>>(
+    path: PathBuf,
+    limits: DecodeLimits,
+) -> impl Future<Output = T> + MaybeSend + 'static {
+    async move {
+        let thumbnail = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || thumbnail::load_or_generate(&path, thumbnail::THUMBNAIL_SIZE, &limits)
+        })
+        .await
+        .ok()
+        .flatten();
+        let handle = thumbnail.map(|thumbnail| {
+            widget::image::Handle::from_rgba(
+                thumbnail.width(),
+                thumbnail.height(),
+                thumbnail.into_vec(),
+            )
+        });
+        Message::ThumbnailLoaded(path, handle).into()
+    }
+}
+/// State of a thumbnail in [`AppModel::thumbnails`].
+#[derive(Clone, Debug)]
+pub enum Thumbnail {
+    Loading,
+    Loaded(widget::image::Handle),
+    Failed,
+}
+/// Stores with edits which are saved with [`Message::SaveEdits`] after [`SAVE_DELAY`],
+/// instead of rewriting them on every keystroke or click.
+#[derive(Debug, Default, Clone, Copy)]
+struct Unsaved {
+    ref_store: bool,
+    albums: bool,
+}
+impl Unsaved {
+    fn any(self) -> bool {
+        self.ref_store || self.albums
+    }
+}
+/// Thumbnails are evicted once more than this many are kept, which use about 256 KiB each.
+const MAX_THUMBNAILS: usize = 1024;
+/// Thumbnails of [`AppModel::thumbnails`], evicting the least recently used ones,
+/// so scrolling through a large library does not keep all of them in memory.
+#[derive(Debug, Default)]
+pub struct Thumbnails {
+    /// Thumbnails with the [`Self::clock`] value of their last use.
+    entries: HashMap<PathBuf, (Thumbnail, u64)>,
+    /// Advanced with every use.
+    clock: u64,
+}
+impl Thumbnails {
+    pub fn get(&self, path: &Path) -> Option<&Thumbnail> {
+        self.entries.get(path).map(|(thumbnail, _)| thumbnail)
+    }
+    /// Marks the thumbnail of `path` as used. Returns `false` if it is not known.
+    pub fn touch(&mut self, path: &Path) -> bool {
+        self.clock += 1;
+        match self.entries.get_mut(path) {
+            Some((_, used)) => {
+                *used = self.clock;
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn insert(&mut self, path: PathBuf, thumbnail: Thumbnail) {
+        self.clock += 1;
+        self.entries.insert(path, (thumbnail, self.clock));
+        // evicting a quarter at once keeps the sorting rare
+        if self.entries.len() > MAX_THUMBNAILS + MAX_THUMBNAILS / 4 {
+            let mut used: Vec<u64> = self.entries.values().map(|(_, used)| *used).collect();
+            used.sort_unstable();
+            let oldest_kept = used[used.len() - MAX_THUMBNAILS];
+            self.entries.retain(|_, (_, used)| *used >= oldest_kept);
+        }
+    }
+    pub fn retain(&mut self, mut keep: impl FnMut(&Path) -> bool) {
+        self.entries.retain(|path, _| keep(path));
     }
 }
 #[derive(Clone, Copy, Debug)]
 pub enum ReferenceLoad {
     PushLast,
     Index(usize),
+    /// The reference is shown in a pane of the reference board.
+    Board,
+    /// The reference is shown in the preview of the browser.
+    Preview,
+}
+impl ReferenceLoad {
+    /// Whether the reference was randomly picked for the figure drawing session,
+    /// so another one can be picked if loading fails.
+    pub fn is_sampled(&self) -> bool {
+        matches!(self, ReferenceLoad::PushLast | ReferenceLoad::Index(_))
+    }
 }
@@ -0,0 +1,235 @@
+//! Zip and cbz archives as source folders, so pose packs do not have to be extracted.
+//!
+//! An entry of an archive is referenced by the path of the archive joined with the name of the
+//! entry, like `poses.zip/standing/01.jpg`. Entries are read into memory when they are decoded.
+//! The last used archives are kept open, so their central directory is only read once.
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Cursor, Read, Seek, Write},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use zip::ZipArchive;
+
+/// File extensions of the supported archives.
+pub const EXTENSIONS: [&str; 2] = ["zip", "cbz"];
+
+/// Number of archives kept open.
+const MAX_OPEN_ARCHIVES: usize = 8;
+/// Entries larger than this are not read into memory by [`open`].
+/// Compressed images are much smaller than the decoded ones, so this only stops broken or
+/// malicious archives.
+const MAX_ENTRY_LEN: u64 = 512 * 1024 * 1024;
+
+type Archive = ZipArchive<BufReader<fs::File>>;
+
+/// An archive kept open with the size and modification time it had when it was opened.
+struct OpenArchive {
+    path: PathBuf,
+    len: u64,
+    modified: Option<SystemTime>,
+    archive: Arc<Mutex<Archive>>,
+}
+
+/// Open archives, the most recently used last.
+static OPEN_ARCHIVES: Mutex<Vec<OpenArchive>> = Mutex::new(Vec::new());
+
+/// A reader over a file on disk or an entry of an archive.
+pub trait ReadSeek: BufRead + Seek {}
+impl<T: BufRead + Seek> ReadSeek for T {}
+
+/// Whether `path` has the extension of a supported archive.
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            EXTENSIONS
+                .iter()
+                .any(|supported| extension.eq_ignore_ascii_case(supported))
+        })
+}
+
+/// Splits `path` into the archive containing it and the name of the entry.
+/// Returns `None` for paths that do not lie in an archive.
+pub fn split(path: &Path) -> Option<(&Path, &Path)> {
+    split_with_metadata(path).map(|(archive, name, _)| (archive, name))
+}
+
+/// Like [`split`], but also returns the metadata of the archive, which is read anyway.
+fn split_with_metadata(path: &Path) -> Option<(&Path, &Path, fs::Metadata)> {
+    let (archive, metadata) = path.ancestors().skip(1).find_map(|ancestor| {
+        is_archive(ancestor)
+            .then(|| fs::metadata(ancestor).ok())
+            .flatten()
+            .filter(fs::Metadata::is_file)
+            .map(|metadata| (ancestor, metadata))
+    })?;
+    Some((archive, path.strip_prefix(archive).ok()?, metadata))
+}
+
+/// Metadata of the file on disk that contains `path`, which is the archive for entries of archives.
+pub fn container_metadata(path: &Path) -> io::Result<fs::Metadata> {
+    match split_with_metadata(path) {
+        Some((_, _, metadata)) => Ok(metadata),
+        None => fs::metadata(path),
+    }
+}
+
+/// Opens the file at `path`, which may be an entry of an archive.
+/// Entries are read into memory, so entries larger than [`MAX_ENTRY_LEN`] are not opened.
+pub fn open(path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+    let Some((archive, name, metadata)) = split_with_metadata(path) else {
+        return Ok(Box::new(BufReader::new(fs::File::open(path)?)));
+    };
+    let content = with_archive(archive, &metadata, |archive| {
+        let entry = archive
+            .by_name(&entry_name(name))
+            .map_err(io::Error::other)?;
+        let size = entry.size();
+        if size > MAX_ENTRY_LEN {
+            return Err(too_large(path));
+        }
+        let mut content = Vec::with_capacity(size as usize);
+        // the size in the archive may be wrong, so the limit is checked while reading as well
+        entry.take(MAX_ENTRY_LEN + 1).read_to_end(&mut content)?;
+        if content.len() as u64 > MAX_ENTRY_LEN {
+            return Err(too_large(path));
+        }
+        Ok(content)
+    })?;
+    Ok(Box::new(Cursor::new(content)))
+}
+
+/// Copies the content of the file at `path`, which may be an entry of an archive, to `writer`.
+/// Unlike [`open`], entries are not read into memory, so they may have any size.
+pub fn copy(path: &Path, writer: &mut impl Write) -> io::Result<u64> {
+    let Some((archive, name, metadata)) = split_with_metadata(path) else {
+        return io::copy(&mut fs::File::open(path)?, writer);
+    };
+    with_archive(archive, &metadata, |archive| {
+        let mut entry = archive
+            .by_name(&entry_name(name))
+            .map_err(io::Error::other)?;
+        io::copy(&mut entry, writer)
+    })
+}
+
+/// Reads up to `len` bytes from the start of the file at `path`, which may be an entry of an archive.
+/// Entries are only decompressed as far as needed.
+pub fn read_header(path: &Path, len: u64) -> io::Result<Vec<u8>> {
+    let mut header = Vec::new();
+    match split_with_metadata(path) {
+        Some((archive, name, metadata)) => {
+            with_archive(archive, &metadata, |archive| {
+                let entry = archive
+                    .by_name(&entry_name(name))
+                    .map_err(io::Error::other)?;
+                entry.take(len).read_to_end(&mut header)
+            })?;
+        }
+        None => {
+            fs::File::open(path)?.take(len).read_to_end(&mut header)?;
+        }
+    }
+    Ok(header)
+}
+
+/// Calls `visit` with the name, uncompressed size and content of every file in the archive at
+/// `archive_path`, until it returns [`ControlFlow::Break`]. Only the read part of an entry is
+/// decompressed, so listing a large archive does not read all of it.
+pub fn for_each_entry(
+    archive_path: &Path,
+    mut visit: impl FnMut(&Path, u64, &mut dyn Read) -> ControlFlow<()>,
+) -> io::Result<ControlFlow<()>> {
+    let mut archive = open_archive(archive_path)?;
+    for index in 0..archive.len() {
+        let mut entry = match archive.by_index(index) {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::warn!("Skipping entry {index} of {archive_path:?} with error {e:?}");
+                continue;
+            }
+        };
+        // names that would escape the archive, like `../x.jpg`, are skipped
+        let Some(name) = entry.enclosed_name() else {
+            continue;
+        };
+        if !entry.is_file() {
+            continue;
+        }
+        let size = entry.size();
+        if visit(&name, size, &mut entry).is_break() {
+            return Ok(ControlFlow::Break(()));
+        }
+    }
+    Ok(ControlFlow::Continue(()))
+}
+
+fn open_archive(path: &Path) -> io::Result<Archive> {
+    ZipArchive::new(BufReader::new(fs::File::open(path)?)).map_err(io::Error::other)
+}
+
+/// Calls `read` with the archive at `path`, which has `metadata`. The archive is kept open for
+/// the next call, unless it changed since. Reads from the same archive wait for each other.
+fn with_archive<T>(
+    path: &Path,
+    metadata: &fs::Metadata,
+    read: impl FnOnce(&mut Archive) -> io::Result<T>,
+) -> io::Result<T> {
+    let len = metadata.len();
+    let modified = metadata.modified().ok();
+    let cached = {
+        let mut open = OPEN_ARCHIVES.lock().unwrap_or_else(PoisonError::into_inner);
+        let position = open.iter().position(|cached| cached.path == path);
+        match position.map(|position| open.remove(position)) {
+            Some(cached) if cached.len == len && cached.modified == modified => {
+                let archive = cached.archive.clone();
+                open.push(cached);
+                Some(archive)
+            }
+            _ => None,
+        }
+    };
+    let archive = match cached {
+        Some(archive) => archive,
+        None => {
+            // opened without holding the lock, as reading the central directory of a large
+            // archive takes a while
+            let archive = Arc::new(Mutex::new(open_archive(path)?));
+            let mut open = OPEN_ARCHIVES.lock().unwrap_or_else(PoisonError::into_inner);
+            open.retain(|cached| cached.path != path);
+            if open.len() >= MAX_OPEN_ARCHIVES {
+                open.remove(0);
+            }
+            open.push(OpenArchive {
+                path: path.to_path_buf(),
+                len,
+                modified,
+                archive: archive.clone(),
+            });
+            archive
+        }
+    };
+    let mut archive = archive.lock().unwrap_or_else(PoisonError::into_inner);
+    read(&mut archive)
+}
+
+fn too_large(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::OutOfMemory,
+        format!("{path:?} is larger than {MAX_ENTRY_LEN} bytes"),
+    )
+}
+
+/// Name of the entry at `relative` inside its archive, which always uses `/` as separator.
+fn entry_name(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
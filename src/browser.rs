@@ -0,0 +1,375 @@
+//! Thumbnail grid to browse and curate the references of the store.
+
+use std::{cell::Cell, ops::Range, path::PathBuf};
+
+use cosmic::{
+    iced::{
+        keyboard::{self, key::Named, Key},
+        widget::scrollable::{AbsoluteOffset, Viewport},
+        Alignment, Length,
+    },
+    iced_widget::{button, responsive},
+    widget::{self, text, Id},
+    Element,
+};
+
+use crate::{
+    animation::{self, Playback, PlaybackTarget},
+    app::{AppModel, Message, Thumbnail},
+    collection::{SmartCollection, SortOrder},
+    export::ExportSource,
+    fl,
+    query::{QueryInput, QueryTarget},
+    reference::{RefStore, Reference},
+    view,
+};
+
+/// Width and height of a cell in the grid.
+pub const CELL_SIZE: f32 = 160.;
+pub const CELL_SPACING: f32 = 8.;
+const ROW_HEIGHT: f32 = CELL_SIZE + CELL_SPACING;
+/// Rows rendered and loaded above and below the visible part of the grid.
+const OVERSCAN_ROWS: usize = 2;
+/// Visible height assumed until the grid reported its real size.
+const DEFAULT_VIEWPORT_HEIGHT: f32 = 1000.;
+
+pub fn scroll_id() -> Id {
+    Id::new("reference-browser")
+}
+
+#[derive(Debug)]
+pub struct BrowserState {
+    /// Only references of the source folder with this path are shown. `None` shows all.
+    pub source_filter: Option<PathBuf>,
+    /// Only references matching this query are shown.
+    pub query: QueryInput,
+    /// Id of the smart collection that is browsed. `None` browses the whole store.
+    pub collection: Option<u32>,
+    /// References shown in the grid, updated by [`BrowserState::refresh`].
+    pub entries: Vec<Reference>,
+    /// Index into [`Self::entries`].
+    pub selected: Option<usize>,
+    /// Whether the selected reference is shown in a large preview instead of the grid.
+    pub preview: bool,
+    pub playback: Playback,
+    pub scroll_offset: f32,
+    pub viewport_height: f32,
+    /// Number of columns of the last rendered grid.
+    /// Written during view, as the available width is only known there.
+    pub columns: Cell<usize>,
+}
+impl Default for BrowserState {
+    fn default() -> Self {
+        BrowserState {
+            source_filter: None,
+            query: QueryInput::default(),
+            collection: None,
+            entries: Vec::new(),
+            selected: None,
+            preview: false,
+            playback: Playback::default(),
+            scroll_offset: 0.,
+            viewport_height: DEFAULT_VIEWPORT_HEIGHT,
+            columns: Cell::new(1),
+        }
+    }
+}
+impl BrowserState {
+    /// Collects the references to show from `ref_store`, keeping the selection if possible.
+    pub fn refresh(&mut self, ref_store: &RefStore) {
+        let selected_path = self.selected_reference().map(|r| r.path.clone());
+        let collection = self.collection.and_then(|id| ref_store.collection(id));
+        let query = match collection.map(SmartCollection::query) {
+            Some(Ok(query)) => query.and(self.query.query.clone()),
+            Some(Err(e)) => {
+                tracing::warn!("Not showing collection with invalid query: {e}");
+                self.entries.clear();
+                self.selected = None;
+                self.preview = false;
+                return;
+            }
+            None => self.query.query.clone(),
+        };
+        let query = &query;
+        self.entries = ref_store
+            .source_folders
+            .iter()
+            .filter(|source| {
+                self.source_filter
+                    .as_ref()
+                    .is_none_or(|filter| filter == &source.path)
+            })
+            .flat_map(|source| {
+                source.children.iter().filter(move |reference| {
+                    query.matches(reference, source, ref_store.metadata(&reference.path))
+                })
+            })
+            .cloned()
+            .collect();
+        collection
+            .map_or(SortOrder::Path, |collection| collection.sort)
+            .sort(&mut self.entries);
+        self.selected = selected_path
+            .and_then(|path| self.entries.iter().position(|r| r.path == path))
+            .or_else(|| (!self.entries.is_empty()).then_some(0));
+        if self.selected.is_none() {
+            self.preview = false;
+        }
+    }
+    pub fn selected_reference(&self) -> Option<&Reference> {
+        self.selected.and_then(|index| self.entries.get(index))
+    }
+    /// The reference shown in the preview, if the preview is open.
+    pub fn preview_reference(&self) -> Option<&Reference> {
+        self.selected_reference().filter(|_| self.preview)
+    }
+    /// Range of [`Self::entries`] that is currently rendered.
+    pub fn visible_range(&self) -> Range<usize> {
+        let columns = self.columns.get().max(1);
+        let first_row = ((self.scroll_offset / ROW_HEIGHT) as usize).saturating_sub(OVERSCAN_ROWS);
+        let last_row = ((self.scroll_offset + self.viewport_height) / ROW_HEIGHT).ceil() as usize
+            + OVERSCAN_ROWS;
+        (first_row * columns).min(self.entries.len())..(last_row * columns).min(self.entries.len())
+    }
+    /// Moves the selection by `dx` cells and `dy` rows, staying inside the grid.
+    pub fn move_selection(&mut self, dx: isize, dy: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let columns = self.columns.get().max(1) as isize;
+        let current = self.selected.unwrap_or_default() as isize;
+        let target = current + dx + dy * columns;
+        self.selected = Some(target.clamp(0, self.entries.len() as isize - 1) as usize);
+        self.playback = Playback::default();
+    }
+    /// Scroll offset that brings the selected cell into view, if it is not visible already.
+    pub fn scroll_to_selection(&self) -> Option<AbsoluteOffset> {
+        let row = self.selected? / self.columns.get().max(1);
+        let top = row as f32 * ROW_HEIGHT;
+        if top < self.scroll_offset {
+            Some(AbsoluteOffset { x: 0., y: top })
+        } else if top + ROW_HEIGHT > self.scroll_offset + self.viewport_height {
+            Some(AbsoluteOffset {
+                x: 0.,
+                y: top + ROW_HEIGHT - self.viewport_height,
+            })
+        } else {
+            None
+        }
+    }
+    pub fn on_scroll(&mut self, viewport: Viewport) {
+        self.scroll_offset = viewport.absolute_offset().y;
+        self.viewport_height = viewport.bounds().height;
+    }
+}
+
+pub fn keypress(key_press: Key) -> Option<Message> {
+    match key_press.as_ref() {
+        keyboard::Key::Named(Named::ArrowLeft) => Some(Message::BrowserMove { dx: -1, dy: 0 }),
+        keyboard::Key::Named(Named::ArrowRight) => Some(Message::BrowserMove { dx: 1, dy: 0 }),
+        keyboard::Key::Named(Named::ArrowUp) => Some(Message::BrowserMove { dx: 0, dy: -1 }),
+        keyboard::Key::Named(Named::ArrowDown) => Some(Message::BrowserMove { dx: 0, dy: 1 }),
+        keyboard::Key::Named(Named::Enter) => Some(Message::BrowserOpenPreview),
+        keyboard::Key::Named(Named::Escape) => Some(Message::BrowserClosePreview),
+        keyboard::Key::Character("a") => Some(Message::ToggleInActiveAlbum),
+        keyboard::Key::Character("x") => Some(Message::HideCurrent),
+        keyboard::Key::Character("m") => Some(Message::FindSimilarToCurrent),
+        _ => None,
+    }
+}
+
+pub fn view(app: &AppModel) -> Element<Message> {
+    let browser = &app.browser;
+    let mut filters = widget::row()
+        .push(source_filter_button(fl!("all_sources"), None, browser))
+        .spacing(8);
+    for source in &app.ref_store.source_folders {
+        let name = source
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| source.path.display().to_string());
+        filters = filters.push(source_filter_button(
+            name,
+            Some(source.path.clone()),
+            browser,
+        ));
+    }
+    let mut bulk = widget::row()
+        .push(text(fl!("set_content_of_shown")))
+        .spacing(8)
+        .align_y(Alignment::Center);
+    for (label, content_override) in view::content_override_options() {
+        bulk = bulk.push(
+            button(text(label).size(14))
+                .padding(3)
+                .on_press(Message::BulkSetContentOverride(content_override)),
+        );
+    }
+    let content = if browser.preview {
+        view_preview(app)
+    } else {
+        view_grid(app)
+    };
+    widget::column()
+        .push(filters)
+        .push(bulk)
+        .push(
+            widget::row()
+                .push(
+                    widget::container(view::query_input(&browser.query, QueryTarget::Browser))
+                        .width(Length::Fill),
+                )
+                .push_maybe(browser.collection.is_none().then(|| {
+                    button(text(fl!("save_as_collection")).size(14))
+                        .padding(3)
+                        .on_press(Message::AddCollection)
+                }))
+                .push(
+                    button(text(fl!("show_hidden")).size(14))
+                        .padding(3)
+                        .on_press(Message::SetQuery(
+                            QueryTarget::Browser,
+                            String::from("is:hidden"),
+                        )),
+                )
+                .push(
+                    button(text(fl!("export")).size(14))
+                        .padding(3)
+                        .on_press(Message::OpenExport(ExportSource::Browser)),
+                )
+                .spacing(8),
+        )
+        .push(content)
+        .spacing(8)
+        .into()
+}
+
+fn source_filter_button<'a>(
+    label: String,
+    filter: Option<PathBuf>,
+    browser: &BrowserState,
+) -> Element<'a, Message> {
+    let label = if browser.source_filter == filter {
+        format!("[{label}]")
+    } else {
+        label
+    };
+    button(text(label).size(14))
+        .padding(3)
+        .on_press(Message::BrowserFilterSource(filter))
+        .into()
+}
+
+/// The grid only creates widgets for the rows around the visible area,
+/// the rest is replaced by empty space of the same height.
+fn view_grid(app: &AppModel) -> Element<Message> {
+    let browser = &app.browser;
+    if browser.entries.is_empty() {
+        return text(fl!("no_references")).into();
+    }
+    responsive(move |size| {
+        let columns = ((size.width + CELL_SPACING) / (CELL_SIZE + CELL_SPACING)).max(1.) as usize;
+        browser.columns.set(columns);
+        let row_count = browser.entries.len().div_ceil(columns);
+        let visible = browser.visible_range();
+        let first_row = visible.start / columns;
+        let last_row = visible.end.div_ceil(columns);
+
+        let mut grid = widget::column()
+            .push(widget::Space::with_height(first_row as f32 * ROW_HEIGHT))
+            .spacing(CELL_SPACING);
+        for row in first_row..last_row {
+            let start = row * columns;
+            let end = (start + columns).min(browser.entries.len());
+            let mut cells = widget::row().spacing(CELL_SPACING);
+            for index in start..end {
+                cells = cells.push(view_cell(app, index));
+            }
+            grid = grid.push(cells);
+        }
+        grid = grid.push(widget::Space::with_height(
+            row_count.saturating_sub(last_row) as f32 * ROW_HEIGHT,
+        ));
+        widget::scrollable(grid)
+            .id(scroll_id())
+            .on_scroll(Message::BrowserScrolled)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .into()
+    })
+    .into()
+}
+
+fn view_cell(app: &AppModel, index: usize) -> Element<Message> {
+    let browser = &app.browser;
+    let reference = &browser.entries[index];
+    let selected = browser.selected == Some(index);
+    let thumbnail: Element<Message> = match app.thumbnails.get(&reference.path) {
+        // hidden references stay visible, so they can be restored
+        Some(Thumbnail::Loaded(handle)) if app.ref_store.is_hidden(&reference.path) => {
+            widget::image(handle.clone()).opacity(0.3).into()
+        }
+        Some(Thumbnail::Loaded(handle)) => widget::image(handle.clone()).into(),
+        Some(Thumbnail::Failed) => text("!").into(),
+        _ => text("…").into(),
+    };
+    // shows that the content rating of the folder is overridden
+    let content_override = app
+        .ref_store
+        .metadata(&reference.path)
+        .and_then(|meta| meta.content_override)
+        .map(|rating| widget::text::caption(view::content_rating_name(rating)));
+    let content = widget::column()
+        .push(widget::container(thumbnail).height(Length::Fill))
+        .push_maybe(content_override)
+        .align_x(Alignment::Center);
+    widget::button::custom(content)
+        .selected(selected)
+        .width(Length::Fixed(CELL_SIZE))
+        .height(Length::Fixed(CELL_SIZE))
+        .on_press(Message::BrowserSelect(index))
+        .into()
+}
+
+fn view_preview(app: &AppModel) -> Element<Message> {
+    let browser = &app.browser;
+    let Some(reference) = browser.selected_reference() else {
+        return text(fl!("no_references")).into();
+    };
+    let header = widget::row()
+        .push(
+            button(text(fl!("back")).size(14))
+                .padding(3)
+                .on_press(Message::BrowserClosePreview),
+        )
+        .push(
+            button(text(fl!("more_like_this")).size(14))
+                .padding(3)
+                .on_press(Message::FindSimilar(reference.path.clone())),
+        )
+        .push(text(reference.path.display().to_string()))
+        .spacing(8)
+        .align_y(Alignment::Center);
+    let mut preview = widget::column()
+        .push(header)
+        .push(view::metadata_editor(app, &reference.path))
+        .push(view::album_picker(app, &reference.path))
+        .spacing(8);
+    match app.ref_store.ref_data.get(&reference.path) {
+        Some(loaded) => {
+            preview = preview
+                .push_maybe(animation::warning(loaded))
+                .push_maybe(loaded.is_animated().then(|| {
+                    animation::controls(loaded, &browser.playback, PlaybackTarget::Preview)
+                }))
+                .push(
+                    animation::view(loaded, &browser.playback)
+                        .width(Length::Fill)
+                        .height(Length::Fill),
+                );
+        }
+        None => preview = preview.push(text(fl!("loading"))),
+    }
+    preview.into()
+}
@@ -0,0 +1,83 @@
+//! Smart collections, which are named queries over all references of the store.
+
+use std::cmp::Reverse;
+
+use crate::{
+    query::{Query, QueryError},
+    reference::Reference,
+};
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct SmartCollection {
+    /// Stays the same when the collection is renamed.
+    pub id: u32,
+    pub name: String,
+    /// Query in the syntax of [`crate::query`]. Stored as text, so it is kept even if it is invalid.
+    pub query: String,
+    #[serde(default)]
+    pub sort: SortOrder,
+}
+impl SmartCollection {
+    pub fn query(&self) -> Result<Query, QueryError> {
+        Query::parse(&self.query)
+    }
+    pub fn apply(&mut self, edit: CollectionEdit) {
+        match edit {
+            CollectionEdit::Rename(name) => self.name = name,
+            CollectionEdit::SetQuery(query) => self.query = query,
+            CollectionEdit::SetSort(sort) => self.sort = sort,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CollectionEdit {
+    Rename(String),
+    SetQuery(String),
+    SetSort(SortOrder),
+}
+
+/// Order in which the references of a collection are browsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum SortOrder {
+    #[default]
+    Path,
+    Name,
+    NewestFirst,
+    OldestFirst,
+    /// By pixel count.
+    LargestFirst,
+}
+impl SortOrder {
+    pub const ALL: [SortOrder; 5] = [
+        SortOrder::Path,
+        SortOrder::Name,
+        SortOrder::NewestFirst,
+        SortOrder::OldestFirst,
+        SortOrder::LargestFirst,
+    ];
+    /// Sorts `references`. References without the sorted property come last.
+    pub fn sort(self, references: &mut [Reference]) {
+        match self {
+            SortOrder::Path => references.sort_by(|a, b| a.path.cmp(&b.path)),
+            SortOrder::Name => references.sort_by(|a, b| {
+                a.path
+                    .file_name()
+                    .cmp(&b.path.file_name())
+                    .then_with(|| a.path.cmp(&b.path))
+            }),
+            SortOrder::NewestFirst => {
+                references.sort_by_key(|reference| Reverse(reference.modified))
+            }
+            SortOrder::OldestFirst => references
+                .sort_by_key(|reference| (reference.modified.is_none(), reference.modified)),
+            SortOrder::LargestFirst => references.sort_by_key(|reference| {
+                Reverse(
+                    reference
+                        .dimensions
+                        .map(|(width, height)| width as u64 * height as u64),
+                )
+            }),
+        }
+    }
+}
@@ -2,8 +2,31 @@
 
 use cosmic::cosmic_config::{self, cosmic_config_derive::CosmicConfigEntry, CosmicConfigEntry};
 
-#[derive(Debug, Default, Clone, CosmicConfigEntry, Eq, PartialEq)]
+use crate::decode::DecodeLimits;
+
+#[derive(Debug, Clone, CosmicConfigEntry, Eq, PartialEq)]
 #[version = 1]
 pub struct Config {
     demo: String,
+    /// Images with more megapixels are decoded downsampled.
+    pub max_image_megapixels: u32,
+    /// Upper bound in MiB for the memory a decoder may allocate for a single image.
+    pub max_decode_memory_mib: u32,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            demo: String::new(),
+            max_image_megapixels: 64,
+            max_decode_memory_mib: 1024,
+        }
+    }
+}
+impl Config {
+    pub fn decode_limits(&self) -> DecodeLimits {
+        DecodeLimits {
+            max_pixels: self.max_image_megapixels as u64 * 1_000_000,
+            max_alloc: self.max_decode_memory_mib as u64 * 1024 * 1024,
+        }
+    }
 }
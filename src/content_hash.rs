@@ -0,0 +1,41 @@
+//! Content hashes as the identity of references, so metadata and albums follow files that were
+//! renamed or moved while the app was not running.
+//!
+//! Hashes are computed in the background after the source folders were scanned and stored in the
+//! index, where they are kept as long as the size and modification time of the file do not change.
+//! Files that vanish from a scan are remembered with their hash until the next hashing pass,
+//! which reconnects them to a new file with the same content.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::archive;
+
+/// First 128 bits of the blake3 hash of the file content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+pub struct ContentHash([u64; 2]);
+
+/// Content hash of a file with the size and modification time in nanoseconds it had when it was
+/// hashed, so the hash is only used while the file did not change since.
+#[derive(Debug, Clone)]
+pub struct HashedFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: u64,
+    pub hash: ContentHash,
+}
+
+/// Hashes the content of the file at `path` by reading all of it.
+/// Entries of archives are decompressed without being held in memory.
+pub fn hash_file(path: &Path) -> io::Result<ContentHash> {
+    let mut hasher = blake3::Hasher::new();
+    archive::copy(path, &mut hasher)?;
+    let hash = hasher.finalize();
+    let word = |index: usize| {
+        let bytes = &hash.as_bytes()[index * 8..(index + 1) * 8];
+        u64::from_le_bytes(bytes.try_into().expect("slice has 8 bytes"))
+    };
+    Ok(ContentHash([word(0), word(1)]))
+}
@@ -0,0 +1,568 @@
+//! Decoding of reference files into frames that can be handed to the ui.
+
+use std::{
+    io::{self, BufRead, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use image::{
+    codecs::{
+        gif::GifDecoder, jpeg::JpegDecoder, png::PngDecoder, tiff::TiffDecoder, webp::WebPDecoder,
+    },
+    error::{DecodingError, LimitError, LimitErrorKind},
+    io::Limits,
+    AnimationDecoder, DynamicImage, Frames, ImageDecoder, ImageError, ImageFormat, ImageResult,
+    RgbaImage,
+};
+
+use crate::{
+    archive,
+    reference::{RefFormat, Reference},
+};
+
+/// Bytes read from the start of a file to detect its format and dimensions while scanning.
+/// Jpegs can store exif data and color profiles in front of their size, which this leaves room for.
+pub const HEADER_LEN: u64 = 128 * 1024;
+/// Delay used for frames which do not specify one.
+/// Browsers treat a delay of zero the same way.
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+/// Delay between the frames of numbered image sequences.
+const SEQUENCE_FRAME_DELAY: Duration = Duration::from_millis(125);
+
+#[derive(Debug, Clone)]
+pub struct DecodedFrame {
+    pub image: RgbaImage,
+    pub delay: Duration,
+}
+
+/// All frames of a reference together with a possible warning about how it was decoded.
+#[derive(Debug, Clone)]
+pub struct Decoded {
+    pub frames: Vec<DecodedFrame>,
+    pub warning: Option<DecodeWarning>,
+}
+
+/// Something the user should know about a successfully decoded reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeWarning {
+    /// The image exceeded [`DecodeLimits::max_pixels`] and was decoded at a reduced size.
+    Downsampled { width: u32, height: u32 },
+}
+
+/// Limits that protect the app from images that are too large to decode at full size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Jpegs and pngs with more pixels are decoded at a reduced size with about this many pixels.
+    /// Other formats can only be decoded at full size.
+    pub max_pixels: u64,
+    /// Upper bound in bytes for the memory a decoder may allocate for a single image.
+    pub max_alloc: u64,
+}
+impl DecodeLimits {
+    fn image_limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        limits.max_alloc = Some(self.max_alloc);
+        limits
+    }
+    /// The size an image of `width` x `height` should be decoded at,
+    /// or `None` if it fits into [`Self::max_pixels`].
+    fn downsampled_size(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let pixels = width as u64 * height as u64;
+        if pixels <= self.max_pixels {
+            return None;
+        }
+        let scale = (self.max_pixels as f64 / pixels as f64).sqrt();
+        Some((
+            ((width as f64 * scale) as u32).max(1),
+            ((height as f64 * scale) as u32).max(1),
+        ))
+    }
+    fn check_alloc(&self, bytes: u64) -> ImageResult<()> {
+        if bytes > self.max_alloc {
+            return Err(ImageError::Limits(LimitError::from_kind(
+                LimitErrorKind::InsufficientMemory,
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Decodes all frames of `reference`, which may be a single image, an animation or an image sequence.
+pub fn decode_reference(
+    reference: &Reference,
+    max_bytes: usize,
+    limits: &DecodeLimits,
+) -> ImageResult<Decoded> {
+    if reference.is_sequence() {
+        decode_sequence(&reference.sequence, max_bytes, limits)
+    } else {
+        decode_frames(&reference.path, max_bytes, limits)
+    }
+}
+
+/// Decodes the images of a numbered sequence as frames of an animation.
+///
+/// Frames are downscaled so that the whole sequence fits into `max_bytes`.
+pub fn decode_sequence(
+    paths: &[PathBuf],
+    max_bytes: usize,
+    limits: &DecodeLimits,
+) -> ImageResult<Decoded> {
+    let max_frame_bytes = max_bytes / paths.len().max(1);
+    let mut frames = Vec::with_capacity(paths.len());
+    let mut warning = None;
+    for path in paths {
+        let img = match decode_still(path, RefFormat::sniff(path), limits) {
+            Ok((img, frame_warning)) => {
+                warning = warning.or(frame_warning);
+                img
+            }
+            Err(e) => {
+                tracing::warn!("skipping frame {path:?} of sequence with error {e:?}");
+                continue;
+            }
+        };
+        let bytes = img.width() as usize * img.height() as usize * 4;
+        let img = if bytes > max_frame_bytes {
+            let scale = (max_frame_bytes as f64 / bytes as f64).sqrt();
+            img.thumbnail(
+                (img.width() as f64 * scale) as u32,
+                (img.height() as f64 * scale) as u32,
+            )
+        } else {
+            img
+        };
+        frames.push(DecodedFrame {
+            image: img.to_rgba8(),
+            delay: SEQUENCE_FRAME_DELAY,
+        });
+    }
+    Ok(Decoded { frames, warning })
+}
+
+/// Decodes the image at `path` into its frames.
+///
+/// Animated gifs and webps are decoded into all their frames until the frames would
+/// exceed `max_bytes`. Still images always decode into a single frame.
+pub fn decode_frames(path: &Path, max_bytes: usize, limits: &DecodeLimits) -> ImageResult<Decoded> {
+    let format = RefFormat::sniff(path);
+    let animation = |frames, icc_profile: Option<Vec<u8>>, orientation| -> ImageResult<Decoded> {
+        let transform = icc_profile.and_then(|icc_profile| srgb_transform(&icc_profile, path));
+        Ok(Decoded {
+            frames: collect_frames(frames, max_bytes, path, |image| {
+                let image = match &transform {
+                    Some(transform) => {
+                        let mut image = image;
+                        transform.apply(&mut image);
+                        image
+                    }
+                    None => image,
+                };
+                apply_orientation(DynamicImage::ImageRgba8(image), orientation).into_rgba8()
+            })?,
+            warning: None,
+        })
+    };
+    match format {
+        Some(RefFormat::Gif) => {
+            let mut reader = archive::open(path)?;
+            let icc_profile = embedded_icc_profile(format, &mut reader);
+            reader.rewind()?;
+            let mut decoder = GifDecoder::new(reader)?;
+            decoder.set_limits(limits.image_limits())?;
+            // gifs have no exif data
+            animation(decoder.into_frames(), icc_profile, 1)
+        }
+        Some(RefFormat::Webp) => {
+            let mut reader = archive::open(path)?;
+            let orientation = read_orientation(&mut reader);
+            reader.rewind()?;
+            let mut decoder = WebPDecoder::new(reader)?;
+            if decoder.has_animation() {
+                limits.check_alloc(decoder.total_bytes())?;
+                let icc_profile = decoder.icc_profile();
+                animation(decoder.into_frames(), icc_profile, orientation)
+            } else {
+                let (img, warning) = decode_still(path, format, limits)?;
+                Ok(Decoded {
+                    frames: vec![still_frame(img)],
+                    warning,
+                })
+            }
+        }
+        _ => {
+            let (img, warning) = decode_still(path, format, limits)?;
+            Ok(Decoded {
+                frames: vec![still_frame(img)],
+                warning,
+            })
+        }
+    }
+}
+
+/// Decodes a single image, converts it to sRGB and rotates it according to its exif orientation.
+///
+/// Jpegs and non-interlaced pngs exceeding [`DecodeLimits::max_pixels`] are decoded at a reduced
+/// size, which is reported as warning. Everything else is decoded at full size, and only if it
+/// fits into [`DecodeLimits::max_alloc`].
+pub fn decode_still(
+    path: &Path,
+    format: Option<RefFormat>,
+    limits: &DecodeLimits,
+) -> ImageResult<(DynamicImage, Option<DecodeWarning>)> {
+    let mut reader = archive::open(path)?;
+    // only reads the header, so the image size is known before anything is allocated
+    let (width, height) = image::io::Reader::new(&mut reader)
+        .with_guessed_format()?
+        .into_dimensions()?;
+    let target = limits.downsampled_size(width, height);
+    reader.rewind()?;
+    let orientation = read_orientation(&mut reader);
+    reader.rewind()?;
+    let (img, icc_profile, target) = match (format, target) {
+        (Some(RefFormat::Jpeg), _) => {
+            let mut decoder = JpegDecoder::new(reader)?;
+            if let Some((target_width, target_height)) = target {
+                decoder.scale(
+                    target_width.min(u16::MAX as u32) as u16,
+                    target_height.min(u16::MAX as u32) as u16,
+                )?;
+            }
+            let (img, icc_profile) = decode_limited(decoder, limits)?;
+            (img, icc_profile, target)
+        }
+        (Some(RefFormat::Png), Some(target)) => {
+            match decode_png_downsampled(&mut reader, target, limits)? {
+                Some((img, icc_profile)) => (img, icc_profile, Some(target)),
+                None => {
+                    reader.rewind()?;
+                    let (img, icc_profile) = decode_limited(PngDecoder::new(reader)?, limits)?;
+                    (img, icc_profile, None)
+                }
+            }
+        }
+        (Some(RefFormat::Png), _) => {
+            let (img, icc_profile) = decode_limited(PngDecoder::new(reader)?, limits)?;
+            (img, icc_profile, None)
+        }
+        (Some(RefFormat::Tiff), _) => {
+            let (img, icc_profile) = decode_limited(TiffDecoder::new(reader)?, limits)?;
+            (img, icc_profile, None)
+        }
+        (Some(RefFormat::Webp), _) => {
+            let (img, icc_profile) = decode_limited(WebPDecoder::new(reader)?, limits)?;
+            (img, icc_profile, None)
+        }
+        // the format is guessed from the content, as file extensions can not be trusted
+        _ => {
+            limits.check_alloc(width as u64 * height as u64 * 4)?;
+            let icc_profile = embedded_icc_profile(format, &mut reader);
+            reader.rewind()?;
+            let mut reader = image::io::Reader::new(reader).with_guessed_format()?;
+            reader.limits(limits.image_limits());
+            (reader.decode()?, icc_profile, None)
+        }
+    };
+    let img = match target {
+        Some((target_width, target_height))
+            if img.width() > target_width || img.height() > target_height =>
+        {
+            img.thumbnail(target_width, target_height)
+        }
+        _ => img,
+    };
+    let img = match icc_profile {
+        Some(icc_profile) => DynamicImage::ImageRgba8(to_srgb(img.to_rgba8(), &icc_profile, path)),
+        None => img,
+    };
+    let warning = target.map(|_| {
+        tracing::warn!(
+            "{path:?} with {width}x{height} pixels exceeds the decode limits and is downsampled"
+        );
+        DecodeWarning::Downsampled { width, height }
+    });
+    Ok((apply_orientation(img, orientation), warning))
+}
+
+/// Decodes the png in `reader` one row at a time into an image of `target` size, averaging all
+/// pixels which fall into the same target pixel, so the image is never held at full size.
+/// Returns `None` for interlaced pngs, which do not store their rows in order.
+fn decode_png_downsampled(
+    reader: impl Read,
+    (target_width, target_height): (u32, u32),
+    limits: &DecodeLimits,
+) -> ImageResult<Option<(DynamicImage, Option<Vec<u8>>)>> {
+    limits.check_alloc(target_width as u64 * target_height as u64 * 4)?;
+    let png_error = |e: png::DecodingError| {
+        ImageError::Decoding(DecodingError::new(ImageFormat::Png.into(), e))
+    };
+    let mut decoder = png::Decoder::new(reader);
+    // palettes, transparency chunks and less than 8 bits per sample are expanded
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut png = decoder.read_info().map_err(png_error)?;
+    let info = png.info();
+    if info.interlaced {
+        return Ok(None);
+    }
+    let (width, height) = (info.width, info.height);
+    let icc_profile = info.icc_profile.as_ref().map(|profile| profile.to_vec());
+    let (color, depth) = png.output_color_type();
+    let channels = color.samples();
+    // 16 bit samples are big endian, so their first byte is the most significant one
+    let sample_len = if depth == png::BitDepth::Sixteen {
+        2
+    } else {
+        1
+    };
+    let mut sums = vec![[0u64; 4]; target_width as usize];
+    let mut counts = vec![0u64; target_width as usize];
+    let mut img = RgbaImage::new(target_width, target_height);
+    let mut flush = |y: u32, sums: &mut [[u64; 4]], counts: &mut [u64]| {
+        for (x, (sum, count)) in sums.iter_mut().zip(counts.iter_mut()).enumerate() {
+            if *count > 0 {
+                let average = sum.map(|channel| (channel / *count) as u8);
+                img.put_pixel(x as u32, y, image::Rgba(average));
+            }
+            *sum = [0; 4];
+            *count = 0;
+        }
+    };
+    let mut target_y = 0;
+    for y in 0..height {
+        let Some(row) = png.next_row().map_err(png_error)? else {
+            break;
+        };
+        let row_target_y = (y as u64 * target_height as u64 / height as u64) as u32;
+        if row_target_y != target_y {
+            flush(target_y, &mut sums, &mut counts);
+            target_y = row_target_y;
+        }
+        for (x, pixel) in row.data().chunks_exact(channels * sample_len).enumerate() {
+            let sample = |channel: usize| u64::from(pixel[channel * sample_len]);
+            let rgba = match channels {
+                1 => [sample(0), sample(0), sample(0), 255],
+                2 => [sample(0), sample(0), sample(0), sample(1)],
+                3 => [sample(0), sample(1), sample(2), 255],
+                _ => [sample(0), sample(1), sample(2), sample(3)],
+            };
+            let target_x = (x as u64 * target_width as u64 / width as u64) as usize;
+            for (sum, value) in sums[target_x].iter_mut().zip(rgba) {
+                *sum += value;
+            }
+            counts[target_x] += 1;
+        }
+    }
+    flush(target_y, &mut sums, &mut counts);
+    Ok(Some((DynamicImage::ImageRgba8(img), icc_profile)))
+}
+
+/// Decodes the image of `decoder` if it fits into `limits`.
+/// Also returns the embedded icc profile.
+fn decode_limited<'a>(
+    mut decoder: impl ImageDecoder<'a>,
+    limits: &DecodeLimits,
+) -> ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+    decoder.set_limits(limits.image_limits())?;
+    limits.check_alloc(decoder.total_bytes())?;
+    let icc_profile = decoder.icc_profile();
+    Ok((DynamicImage::from_decoder(decoder)?, icc_profile))
+}
+
+/// Converts `img` from the color space described by `icc_profile` to sRGB.
+/// Returns the image unchanged if the profile can not be used.
+fn to_srgb(mut img: RgbaImage, icc_profile: &[u8], path: &Path) -> RgbaImage {
+    if let Some(transform) = srgb_transform(icc_profile, path) {
+        transform.apply(&mut img);
+    }
+    img
+}
+
+/// Transform from the color space described by `icc_profile` to sRGB.
+/// Returns `None` if the profile can not be used.
+fn srgb_transform(icc_profile: &[u8], path: &Path) -> Option<qcms::Transform> {
+    let Some(input) = qcms::Profile::new_from_slice(icc_profile, false) else {
+        tracing::warn!("ignoring invalid icc profile of {path:?}");
+        return None;
+    };
+    let output = qcms::Profile::new_sRGB();
+    let transform = qcms::Transform::new(
+        &input,
+        &output,
+        qcms::DataType::RGBA8,
+        qcms::Intent::Perceptual,
+    );
+    if transform.is_none() {
+        tracing::warn!("can not convert icc profile of {path:?} to sRGB");
+    }
+    transform
+}
+
+/// Reads the icc profile embedded in a bmp or gif, whose decoders do not expose it.
+/// Returns `None` for other formats.
+fn embedded_icc_profile<R: Read + Seek>(
+    format: Option<RefFormat>,
+    reader: &mut R,
+) -> Option<Vec<u8>> {
+    match format {
+        Some(RefFormat::Bmp) => bmp_icc_profile(reader).ok().flatten(),
+        Some(RefFormat::Gif) => gif_icc_profile(reader).ok().flatten(),
+        _ => None,
+    }
+}
+
+/// Icc profiles are a few kilobytes, larger ones are broken.
+const MAX_ICC_PROFILE_LEN: usize = 4 * 1024 * 1024;
+
+/// Reads the profile of a bmp with a version 5 info header and an embedded profile.
+fn bmp_icc_profile<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    const INFO_HEADER: u64 = 14;
+    const V5_HEADER_LEN: u32 = 124;
+    const PROFILE_EMBEDDED: u32 = u32::from_be_bytes(*b"MBED");
+    let mut header = [0; V5_HEADER_LEN as usize];
+    reader.seek(SeekFrom::Start(INFO_HEADER))?;
+    reader.read_exact(&mut header)?;
+    let field =
+        |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().expect("4 bytes"));
+    if field(0) < V5_HEADER_LEN || field(56) != PROFILE_EMBEDDED {
+        return Ok(None);
+    }
+    // the offset of the profile is relative to the info header
+    let (offset, len) = (field(112), field(116) as usize);
+    if len > MAX_ICC_PROFILE_LEN {
+        return Ok(None);
+    }
+    let mut profile = vec![0; len];
+    reader.seek(SeekFrom::Start(INFO_HEADER + u64::from(offset)))?;
+    reader.read_exact(&mut profile)?;
+    Ok(Some(profile))
+}
+
+/// Reads the profile of a gif from its `ICCRGBG1012` application extension, which comes before
+/// the first image.
+fn gif_icc_profile<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let byte = |reader: &mut R| -> io::Result<u8> {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        Ok(byte[0])
+    };
+    // signature, version and the logical screen descriptor up to its flags
+    reader.seek(SeekFrom::Start(10))?;
+    let flags = byte(reader)?;
+    let global_colors = if flags & 0x80 != 0 {
+        3 << ((flags & 0x07) + 1)
+    } else {
+        0
+    };
+    reader.seek(SeekFrom::Current(2 + global_colors))?;
+    loop {
+        // everything but extensions, like the first image, ends the search
+        if byte(reader)? != 0x21 {
+            return Ok(None);
+        }
+        let label = byte(reader)?;
+        let mut blocks: Vec<Vec<u8>> = Vec::new();
+        let mut len = 0;
+        loop {
+            let block_len = byte(reader)? as usize;
+            if block_len == 0 {
+                break;
+            }
+            len += block_len;
+            if len > MAX_ICC_PROFILE_LEN {
+                return Ok(None);
+            }
+            let mut block = vec![0; block_len];
+            reader.read_exact(&mut block)?;
+            blocks.push(block);
+        }
+        if label == 0xFF && blocks.first().is_some_and(|id| id == b"ICCRGBG1012") {
+            return Ok(Some(blocks[1..].concat()));
+        }
+    }
+}
+
+/// Width and height of the image in `reader` as it is displayed, after applying its exif orientation.
+/// `reader` only needs to hold the start of the image, like the first [`HEADER_LEN`] bytes.
+/// Returns `None` if the size is not part of them.
+pub fn dimensions_from(mut reader: impl BufRead + Seek) -> Option<(u32, u32)> {
+    let (width, height) = image::io::Reader::new(&mut reader)
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()?;
+    reader.rewind().ok()?;
+    // orientations 5 to 8 rotate the image by 90 degrees
+    if read_orientation(&mut reader) >= 5 {
+        Some((height, width))
+    } else {
+        Some((width, height))
+    }
+}
+
+/// Reads the exif orientation tag of the image in `reader`.
+/// Returns 1, meaning no transformation, if there is none.
+fn read_orientation<R: BufRead + Seek>(reader: &mut R) -> u32 {
+    exif::Reader::new()
+        .read_from_container(reader)
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+fn apply_orientation(img: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+fn still_frame(img: DynamicImage) -> DecodedFrame {
+    DecodedFrame {
+        image: img.to_rgba8(),
+        delay: Duration::ZERO,
+    }
+}
+
+/// Collects the frames of an animation, passing each through `finish`, e.g. to convert its colors.
+fn collect_frames(
+    frames: Frames,
+    max_bytes: usize,
+    path: &Path,
+    finish: impl Fn(RgbaImage) -> RgbaImage,
+) -> ImageResult<Vec<DecodedFrame>> {
+    let mut decoded = Vec::new();
+    let mut bytes = 0;
+    for frame in frames {
+        let frame = frame?;
+        let delay = Duration::from(frame.delay());
+        let image = frame.into_buffer();
+        bytes += image.as_raw().len();
+        // always keep the first frame so the reference can at least be shown as still image
+        if bytes > max_bytes && !decoded.is_empty() {
+            tracing::warn!(
+                "animation {path:?} exceeds the image cache budget. Only the first {} frames are used",
+                decoded.len()
+            );
+            break;
+        }
+        decoded.push(DecodedFrame {
+            image: finish(image),
+            delay: if delay.is_zero() {
+                DEFAULT_FRAME_DELAY
+            } else {
+                delay
+            },
+        });
+    }
+    Ok(decoded)
+}
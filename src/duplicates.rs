@@ -0,0 +1,175 @@
+//! Detection of near-duplicate references, e.g. the same pose pack downloaded into two folders.
+//!
+//! References are compared by a difference hash of their cached thumbnail: the thumbnail is
+//! shrunk to 9x8 grey pixels and every bit records whether a pixel is brighter than its right
+//! neighbour. Resized or recompressed copies of an image only differ in a few bits.
+//! Hashes are cached with the thumbnails, so unchanged files are not decoded again.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use image::{imageops, RgbaImage};
+
+use crate::{decode::DecodeLimits, thumbnail};
+
+const HASHES_FILENAME: &str = "perceptual_hashes.ron";
+
+pub type PerceptualHash = u64;
+/// Hashes that differ in at most this many bits belong to the same group.
+pub const MAX_DISTANCE: u32 = 3;
+/// Candidates are found by splitting the hashes into `MAX_DISTANCE + 1` bands.
+/// Two hashes within [`MAX_DISTANCE`] share at least one band, so only hashes in the same
+/// bucket of a band have to be compared.
+const BANDS: u32 = MAX_DISTANCE + 1;
+const BAND_BITS: u32 = PerceptualHash::BITS / BANDS;
+
+/// Difference hash of `img`.
+pub fn difference_hash(img: &RgbaImage) -> PerceptualHash {
+    let grey = imageops::grayscale(img);
+    let small = imageops::resize(&grey, 9, 8, imageops::FilterType::Triangle);
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = small.get_pixel(x, y).0[0] > small.get_pixel(x + 1, y).0[0];
+            hash = hash << 1 | brighter as PerceptualHash;
+        }
+    }
+    hash
+}
+
+/// Perceptual hashes of `paths`. Paths that can not be decoded are left out.
+///
+/// Only files without a cached hash are read, through their thumbnail.
+pub fn hash_all(paths: &[PathBuf], limits: &DecodeLimits) -> HashMap<PathBuf, PerceptualHash> {
+    thumbnail::derive_cached(paths, HASHES_FILENAME, limits, difference_hash)
+}
+
+/// Groups of references whose perceptual hashes are close.
+#[derive(Debug, Clone, Default)]
+pub struct DuplicateGroups {
+    /// Groups of at least two paths, each sorted by path.
+    pub groups: Vec<Vec<PathBuf>>,
+    /// Index into [`Self::groups`] for every path that has duplicates.
+    group_of: HashMap<PathBuf, usize>,
+}
+impl DuplicateGroups {
+    /// Groups `hashes` which are at most [`MAX_DISTANCE`] bits apart from the first reference
+    /// of their group, by path. Grouping is not transitive, as chains of similar hashes, like the
+    /// ones of simple sketches on white, would join many distinct references into one group.
+    pub fn find(hashes: &HashMap<PathBuf, PerceptualHash>) -> DuplicateGroups {
+        let mut entries: Vec<(&PathBuf, PerceptualHash)> =
+            hashes.iter().map(|(path, hash)| (path, *hash)).collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        let band_key =
+            |hash: PerceptualHash, band: u32| hash >> (band * BAND_BITS) & ((1 << BAND_BITS) - 1);
+        let buckets: Vec<HashMap<PerceptualHash, Vec<usize>>> = (0..BANDS)
+            .map(|band| {
+                let mut buckets: HashMap<PerceptualHash, Vec<usize>> = HashMap::new();
+                for (index, (_, hash)) in entries.iter().enumerate() {
+                    buckets
+                        .entry(band_key(*hash, band))
+                        .or_default()
+                        .push(index);
+                }
+                buckets
+            })
+            .collect();
+        let mut grouped = vec![false; entries.len()];
+        let mut groups: Vec<Vec<PathBuf>> = Vec::new();
+        for (first, (path, hash)) in entries.iter().enumerate() {
+            if grouped[first] {
+                continue;
+            }
+            grouped[first] = true;
+            let mut group = vec![path.to_path_buf()];
+            for (band, buckets) in (0..BANDS).zip(&buckets) {
+                for &other in &buckets[&band_key(*hash, band)] {
+                    if !grouped[other] && (hash ^ entries[other].1).count_ones() <= MAX_DISTANCE {
+                        grouped[other] = true;
+                        group.push(entries[other].0.to_path_buf());
+                    }
+                }
+            }
+            if group.len() > 1 {
+                group.sort_unstable();
+                groups.push(group);
+            }
+        }
+        let group_of = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(index, group)| group.iter().map(move |path| (path.clone(), index)))
+            .collect();
+        DuplicateGroups { groups, group_of }
+    }
+    /// Index of the group `path` belongs to, if it has duplicates.
+    pub fn group_of(&self, path: &Path) -> Option<usize> {
+        self.group_of.get(path).copied()
+    }
+    pub fn get(&self, index: usize) -> Option<&[PathBuf]> {
+        self.groups.get(index).map(Vec::as_slice)
+    }
+    /// Number of references left out of sessions, as only one of each group is sampled.
+    pub fn skipped_count(&self) -> usize {
+        self.groups.iter().map(|group| group.len() - 1).sum()
+    }
+}
+
+/// State of the background search for duplicates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchState {
+    #[default]
+    Idle,
+    Running,
+    /// Running, but the source folders changed since it started, so it is started again.
+    Outdated,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(hashes: &[(&str, PerceptualHash)]) -> DuplicateGroups {
+        let hashes = hashes
+            .iter()
+            .map(|(path, hash)| (PathBuf::from(path), *hash))
+            .collect();
+        DuplicateGroups::find(&hashes)
+    }
+    /// A hash with one bit set in each of the first `bands` bands.
+    fn bit_per_band(bands: u32) -> PerceptualHash {
+        (0..bands).map(|band| 1 << (band * BAND_BITS)).sum()
+    }
+
+    #[test]
+    fn hashes_within_max_distance_are_grouped() {
+        let duplicates = find(&[("a", 0), ("b", 0b111), ("c", bit_per_band(3))]);
+        assert_eq!(
+            duplicates.groups,
+            [[PathBuf::from("a"), PathBuf::from("b"), PathBuf::from("c")]]
+        );
+        assert_eq!(duplicates.group_of(Path::new("c")), Some(0));
+        assert_eq!(duplicates.skipped_count(), 2);
+    }
+
+    #[test]
+    fn hashes_beyond_max_distance_are_not_grouped() {
+        let duplicates = find(&[("a", 0), ("b", 0b1111), ("c", bit_per_band(4))]);
+        assert!(duplicates.groups.is_empty());
+        assert_eq!(duplicates.group_of(Path::new("a")), None);
+        assert_eq!(duplicates.skipped_count(), 0);
+    }
+
+    #[test]
+    fn groups_are_not_chained() {
+        // "b" is close to both, but "a" and "c" are 6 bits apart
+        let duplicates = find(&[("c", 0b111111), ("a", 0), ("b", 0b111)]);
+        assert_eq!(
+            duplicates.groups,
+            [[PathBuf::from("a"), PathBuf::from("b")]]
+        );
+        assert_eq!(duplicates.group_of(Path::new("c")), None);
+    }
+}
@@ -0,0 +1,178 @@
+//! Export of a selection of references into a folder, so it can be handed to other tools.
+
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use tracing::warn;
+
+use crate::archive;
+
+/// How the exported files are created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportMode {
+    /// Symbolic links to the original files, which takes no extra space.
+    /// Entries of archives are extracted instead.
+    #[default]
+    Symlink,
+    /// Copies of the files, for targets like a tablet that can not follow links.
+    Copy,
+}
+impl ExportMode {
+    pub const ALL: [ExportMode; 2] = [ExportMode::Symlink, ExportMode::Copy];
+}
+
+/// How the exported files are named.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportNames {
+    /// Keeps the folder structure below the source folder, in a folder named like the source.
+    #[default]
+    Keep,
+    /// All files directly in the target folder. Duplicate names get a number appended.
+    Flatten,
+    /// All files directly in the target folder, prefixed with their position in the selection.
+    Numbered,
+}
+impl ExportNames {
+    pub const ALL: [ExportNames; 3] = [
+        ExportNames::Keep,
+        ExportNames::Flatten,
+        ExportNames::Numbered,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExportOptions {
+    pub mode: ExportMode,
+    pub names: ExportNames,
+}
+
+/// A file to export.
+#[derive(Debug, Clone)]
+pub struct ExportItem {
+    /// Source folder the file was found in.
+    pub source: PathBuf,
+    /// Files of the reference, more than one for image sequences.
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExportReport {
+    pub exported: usize,
+    pub failed: usize,
+}
+
+/// Exports the files of `items` into `target` in their order.
+///
+/// Files that can not be exported, e.g. because the target file already exists, are
+/// logged and counted as failed instead of aborting the export.
+/// Copies read every file in full, and entries of archives are always extracted.
+pub fn export(
+    items: &[ExportItem],
+    target: &Path,
+    options: ExportOptions,
+) -> io::Result<ExportReport> {
+    fs::create_dir_all(target)?;
+    let mut report = ExportReport::default();
+    let mut used_names = HashSet::new();
+    for (index, item) in items.iter().enumerate() {
+        for file in &item.files {
+            let Some(destination) =
+                destination(item, file, index, target, options.names, &mut used_names)
+            else {
+                warn!("Can not export {file:?} as it has no file name");
+                report.failed += 1;
+                continue;
+            };
+            match export_file(file, &destination, options.mode) {
+                Ok(()) => report.exported += 1,
+                Err(e) => {
+                    warn!("Could not export {file:?} to {destination:?} with error {e:?}");
+                    report.failed += 1;
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn destination(
+    item: &ExportItem,
+    file: &Path,
+    index: usize,
+    target: &Path,
+    names: ExportNames,
+    used_names: &mut HashSet<OsString>,
+) -> Option<PathBuf> {
+    let file_name = file.file_name()?;
+    match names {
+        ExportNames::Keep => {
+            let source_name = item.source.file_name().unwrap_or(item.source.as_os_str());
+            let relative = file
+                .strip_prefix(&item.source)
+                .unwrap_or(Path::new(file_name));
+            Some(target.join(source_name).join(relative))
+        }
+        ExportNames::Flatten => {
+            let mut name = file_name.to_os_string();
+            let stem = file.file_stem().unwrap_or(file_name).to_string_lossy();
+            let extension = file.extension().map(|e| e.to_string_lossy());
+            let mut number = 1;
+            while !used_names.insert(name.clone()) {
+                number += 1;
+                name = match &extension {
+                    Some(extension) => format!("{stem}_{number}.{extension}").into(),
+                    None => format!("{stem}_{number}").into(),
+                };
+            }
+            Some(target.join(name))
+        }
+        ExportNames::Numbered => {
+            Some(target.join(format!("{:04}_{}", index + 1, file_name.to_string_lossy())))
+        }
+    }
+}
+
+fn export_file(file: &Path, destination: &Path, mode: ExportMode) -> io::Result<()> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // entries of archives can not be linked, so they are extracted in both modes
+    if archive::split(file).is_some() {
+        let mut extracted = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(destination)?;
+        archive::copy(file, &mut extracted)?;
+        return Ok(());
+    }
+    match mode {
+        ExportMode::Symlink => {
+            // relative links would break as soon as the export is moved
+            let original = fs::canonicalize(file)?;
+            symlink::symlink_file(original, destination)
+        }
+        ExportMode::Copy => {
+            if destination.exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "destination already exists",
+                ));
+            }
+            fs::copy(file, destination).map(|_| ())
+        }
+    }
+}
+
+/// The references an export is started for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportSource {
+    /// The references currently shown in the browser, which may be a smart collection.
+    #[default]
+    Browser,
+    /// The album with this id.
+    Album(u32),
+}
@@ -1,39 +1,124 @@
-use std::time::{Duration, Instant};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use cosmic::iced::keyboard::{self, Key};
 use tracing::info;
 
-use crate::{app::Message, reference::Reference};
+use crate::{
+    animation::{Playback, PlaybackAction, PlaybackTarget},
+    app::Message,
+    query::QueryInput,
+    reference::{ContentRating, Reference},
+};
 
 #[derive(Debug)]
 pub struct FigureDrawingState {
     pub current_ref: Option<usize>,
     pub history: Vec<Reference>,
-    pub sfw_only: bool,
+    /// Only references rated at most this are sampled.
+    pub max_rating: ContentRating,
+    /// Only references matching this query are sampled.
+    pub query: QueryInput,
+    /// References are sampled from this source.
+    pub source: SessionSource,
     pub duration_per_image: Duration,
     pub last_fetched: Instant,
+    /// Playback of the current reference if it is animated.
+    pub playback: Playback,
+    /// References that could not be loaded, which are not sampled again this session.
+    pub failed: HashSet<PathBuf>,
+    /// Loads that failed in a row, see [`MAX_FAILED_LOADS`].
+    pub failed_in_row: u32,
 }
 impl Default for FigureDrawingState {
     fn default() -> Self {
         FigureDrawingState {
             current_ref: None,
             history: Vec::new(),
-            sfw_only: true,
+            max_rating: ContentRating::General,
+            query: QueryInput::default(),
+            source: SessionSource::default(),
             duration_per_image: Duration::from_secs(1 * 60),
             last_fetched: Instant::now(),
+            playback: Playback::default(),
+            failed: HashSet::new(),
+            failed_in_row: 0,
         }
     }
 }
+impl FigureDrawingState {
+    /// Removes the reference at `path`, which could not be loaded, from the history and excludes it
+    /// from sampling. `index` is its position in the history, if it was loaded from there.
+    ///
+    /// Returns whether another reference should be loaded instead, which stops after
+    /// [`MAX_FAILED_LOADS`] failures in a row.
+    pub fn remove_failed(&mut self, path: &Path, index: Option<usize>) -> bool {
+        let index = index
+            .filter(|index| self.history.get(*index).is_some_and(|r| r.path == path))
+            .or_else(|| self.history.iter().rposition(|r| r.path == path));
+        if let Some(index) = index {
+            self.history.remove(index);
+            self.current_ref = match self.current_ref {
+                _ if self.history.is_empty() => None,
+                Some(current) if current > index => Some(current - 1),
+                Some(current) => Some(current.min(self.history.len() - 1)),
+                None => None,
+            };
+        }
+        self.failed.insert(path.to_path_buf());
+        self.failed_in_row += 1;
+        self.failed_in_row < MAX_FAILED_LOADS
+    }
+}
+/// Loads that may fail in a row before no other reference is tried,
+/// e.g. when all sampled files lie on a drive that was just unmounted.
+pub const MAX_FAILED_LOADS: u32 = 5;
+/// The references a figure drawing session samples from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SessionSource {
+    #[default]
+    All,
+    /// The smart collection with this id.
+    Collection(u32),
+    /// The album with this id.
+    Album(u32),
+    /// The results of the last "more like this" search.
+    Similar,
+}
+
 pub fn keypress(key_press: Key) -> Option<Message> {
     match key_press {
+        keyboard::Key::Named(keyboard::key::Named::Space) => Some(Message::Playback(
+            PlaybackTarget::FigureDrawing,
+            PlaybackAction::TogglePlay,
+        )),
         keyboard::Key::Named(_name) => None,
         keyboard::Key::Character(c) => {
             info!("registered keyboard input: {c}");
             let c = c.chars().next().unwrap();
-            if c == 'l' {
+            if c == 'x' {
+                Some(Message::HideCurrent)
+            } else if c == 'a' {
+                Some(Message::ToggleInActiveAlbum)
+            } else if c == 'm' {
+                Some(Message::FindSimilarToCurrent)
+            } else if c == 'l' {
                 Some(Message::IncreaseReferenceCounter { amount: 1 }.into())
             } else if c == 'h' {
                 Some(Message::IncreaseReferenceCounter { amount: -1 }.into())
+            } else if c == ',' {
+                Some(Message::Playback(
+                    PlaybackTarget::FigureDrawing,
+                    PlaybackAction::Step(-1),
+                ))
+            } else if c == '.' {
+                Some(Message::Playback(
+                    PlaybackTarget::FigureDrawing,
+                    PlaybackAction::Step(1),
+                ))
             } else {
                 None
             }
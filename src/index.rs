@@ -0,0 +1,518 @@
+//! Persisted index of the files in the source folders, so startup does not read every file again.
+//!
+//! The modification time of a directory changes when entries are added, removed or renamed in it.
+//! Directories that still have their indexed modification time are not listed again, and files
+//! whose size and modification time did not change keep their indexed format and dimensions.
+//! Files rewritten in place do not change their directory, so they keep their indexed data until
+//! the directory changes. While the app runs, [`crate::watch`] picks them up instead.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs,
+    io::{Cursor, Read},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use tracing::warn;
+
+use crate::{
+    archive,
+    content_hash::{self, ContentHash, HashedFile},
+    decode, io,
+    reference::{self, RefFormat, Reference, RelocationReport, SourceFolder},
+    scan_options::{self, ScanFilter, ScanOptions},
+};
+
+pub const INDEX_FILENAME: &str = "index.ron";
+/// Modification time of invalidated directories, which no directory on disk has.
+const STALE: u64 = u64::MAX;
+
+/// Indices of all source folders keyed by [`SourceFolder::path`].
+pub fn try_load() -> Option<HashMap<PathBuf, SourceIndex>> {
+    io::try_load(INDEX_FILENAME)
+}
+pub fn save_to_disk(sources: &[SourceFolder]) -> Option<()> {
+    let index: HashMap<&Path, &SourceIndex> = sources
+        .iter()
+        .map(|source| (source.path.as_path(), &source.index))
+        .collect();
+    io::save_to_disk(&index, INDEX_FILENAME)
+}
+
+/// Result of the last scan of a source folder.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct SourceIndex {
+    /// Formats the folder was scanned for. The index is not used if they changed since.
+    formats: BTreeSet<RefFormat>,
+    /// Options the folder was scanned with. The index is not used if they changed since.
+    #[serde(default)]
+    options: ScanOptions,
+    /// Whether the folder was the mount point of a drive.
+    #[serde(default)]
+    mount_point: bool,
+    /// Modification times of all scanned directories.
+    directories: HashMap<PathBuf, u64>,
+    /// All files of the scanned formats, before they are grouped into sequences.
+    files: Vec<IndexedFile>,
+    /// Hashes of files that were hashed and vanished since, see [`crate::content_hash`].
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    vanished: HashMap<PathBuf, ContentHash>,
+}
+/// Size and modification time of an entry of an archive are the ones of the archive,
+/// so they can be checked without opening it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+struct IndexedFile {
+    reference: Reference,
+    size: u64,
+    /// Modification time in nanoseconds since the unix epoch.
+    modified: u64,
+}
+impl SourceIndex {
+    /// Scans `root` for files of `formats`, only listing directories that changed since `self`.
+    /// Calls `progress` with every directory that is scanned and the number of files found so far.
+    /// Returns `None` if `progress` cancelled the scan.
+    ///
+    /// Reads the metadata of every entry of the listed directories,
+    /// and the header of every file that is new or changed.
+    pub fn rescan(
+        &self,
+        root: &Path,
+        formats: &BTreeSet<RefFormat>,
+        options: &ScanOptions,
+        progress: &mut dyn FnMut(&Path, usize) -> ControlFlow<()>,
+    ) -> Option<SourceIndex> {
+        let previous =
+            (self.formats == *formats && self.options == *options).then(|| Lookup::new(self));
+        let filter = options.filter().unwrap_or_else(|e| {
+            warn!("Ignoring the patterns of source {root:?} as they are invalid: {e}");
+            ScanFilter::default()
+        });
+        let mut index = SourceIndex {
+            formats: formats.clone(),
+            options: options.clone(),
+            mount_point: is_mount_point(root),
+            ..SourceIndex::default()
+        };
+        let mut walk = Walk {
+            root,
+            options,
+            filter,
+            previous,
+            progress,
+        };
+        let scanned = if archive::is_archive(root) && root.is_file() {
+            index.scan_archive(root, &mut walk)
+        } else {
+            index.scan_dir(root, 0, &mut walk)
+        };
+        if scanned.is_break() {
+            return None;
+        }
+        // unchanged directories add their files before the ones of their subdirectories
+        index
+            .files
+            .sort_unstable_by(|a, b| a.reference.path.cmp(&b.reference.path));
+        Some(index)
+    }
+    /// Whether the source folder at `root`, which was scanned into `self`, can be scanned now.
+    ///
+    /// A folder that was a mount point but is not one anymore is the empty directory left behind
+    /// by an unmounted drive. Scanning it would lose all references of the drive.
+    pub fn is_available(&self, root: &Path) -> bool {
+        let Ok(metadata) = fs::metadata(root) else {
+            return false;
+        };
+        let readable = if metadata.is_dir() {
+            fs::read_dir(root).is_ok()
+        } else {
+            archive::is_archive(root) && fs::File::open(root).is_ok()
+        };
+        readable && (!self.mount_point || is_mount_point(root))
+    }
+    /// Makes the next scan list the directories at and around `paths` again, which re-reads their
+    /// changed files. Files rewritten in place do not change their directory, so the watcher
+    /// reports them here.
+    pub fn invalidate<'p>(&mut self, paths: impl IntoIterator<Item = &'p Path>) {
+        for path in paths {
+            for (dir, modified) in &mut self.directories {
+                if path.parent() == Some(dir.as_path()) || dir.starts_with(path) {
+                    *modified = STALE;
+                }
+            }
+        }
+    }
+    /// Options of the last scan.
+    pub fn options(&self) -> &ScanOptions {
+        &self.options
+    }
+    /// The indexed references, before they are grouped into sequences.
+    pub fn references(&self) -> impl Iterator<Item = Reference> + '_ {
+        self.files.iter().map(|file| file.reference.clone())
+    }
+    /// Moves all paths from below `from` to below `to`, after the source folder was moved.
+    pub fn rebase(&mut self, from: &Path, to: &Path) {
+        self.directories = std::mem::take(&mut self.directories)
+            .into_iter()
+            .map(|(dir, modified)| (reference::rebase(&dir, from, to).unwrap_or(dir), modified))
+            .collect();
+        for file in &mut self.files {
+            if let Some(path) = reference::rebase(&file.reference.path, from, to) {
+                file.reference.path = path;
+            }
+        }
+        self.vanished = std::mem::take(&mut self.vanished)
+            .into_iter()
+            .map(|(path, hash)| (reference::rebase(&path, from, to).unwrap_or(path), hash))
+            .collect();
+        // the next scan checks whether the new location is a mount point
+        self.mount_point = false;
+    }
+    /// Checks whether the indexed files still exist with their indexed size at `source`.
+    /// Only their metadata is read.
+    pub fn verify(&self, source: &Path) -> RelocationReport {
+        let mut report = RelocationReport {
+            source: source.to_path_buf(),
+            checked: self.files.len(),
+            ..RelocationReport::default()
+        };
+        for file in &self.files {
+            let path = &file.reference.path;
+            match archive::container_metadata(path) {
+                Ok(metadata) if metadata.len() == file.size => {}
+                Ok(_) => report.changed.push(path.clone()),
+                Err(_) => report.missing.push(path.clone()),
+            }
+        }
+        report
+    }
+    /// Remembers the hashed files of `previous` which are not part of `self` anymore,
+    /// so they can be reconnected once the files of `self` are hashed.
+    pub fn inherit_vanished(&mut self, previous: &SourceIndex) {
+        let present: HashSet<&Path> = self
+            .files
+            .iter()
+            .map(|file| file.reference.path.as_path())
+            .collect();
+        let vanished = previous
+            .vanished
+            .iter()
+            .map(|(path, hash)| (path, *hash))
+            .chain(previous.hashes())
+            .filter(|(path, _)| !present.contains(path.as_path()))
+            .map(|(path, hash)| (path.clone(), hash))
+            .collect::<Vec<_>>();
+        self.vanished.extend(vanished);
+    }
+    pub fn take_vanished(&mut self) -> HashMap<PathBuf, ContentHash> {
+        std::mem::take(&mut self.vanished)
+    }
+    pub fn remember_vanished(&mut self, path: PathBuf, hash: ContentHash) {
+        self.vanished.insert(path, hash);
+    }
+    /// Paths of all files with their indexed size and modification time in nanoseconds.
+    pub fn file_states(&self) -> impl Iterator<Item = (&Path, u64, u64)> + '_ {
+        self.files
+            .iter()
+            .map(|file| (file.reference.path.as_path(), file.size, file.modified))
+    }
+    /// Paths and content hashes of all hashed files.
+    pub fn hashes(&self) -> impl Iterator<Item = (&PathBuf, ContentHash)> + '_ {
+        self.files
+            .iter()
+            .filter_map(|file| Some((&file.reference.path, file.reference.hash?)))
+    }
+    /// Paths of the files that have no content hash yet,
+    /// with their indexed size and modification time in nanoseconds.
+    pub fn unhashed(&self) -> impl Iterator<Item = (&Path, u64, u64)> + '_ {
+        self.files
+            .iter()
+            .filter(|file| file.reference.hash.is_none())
+            .map(|file| (file.reference.path.as_path(), file.size, file.modified))
+    }
+    /// Takes the hashes of `hashed` for files that did not change since they were hashed.
+    /// Returns whether any file got a hash.
+    pub fn insert_hashes(&mut self, hashed: &[HashedFile]) -> bool {
+        let hashes: HashMap<&Path, &HashedFile> = hashed
+            .iter()
+            .map(|file| (file.path.as_path(), file))
+            .collect();
+        let mut inserted = false;
+        for file in self
+            .files
+            .iter_mut()
+            .filter(|file| file.reference.hash.is_none())
+        {
+            if let Some(other) = hashes
+                .get(file.reference.path.as_path())
+                .filter(|other| other.size == file.size && other.modified == file.modified)
+            {
+                file.reference.hash = Some(other.hash);
+                inserted = true;
+            }
+        }
+        inserted
+    }
+    /// Takes the hashes of `hashed` for files that did not change since it was hashed.
+    pub fn merge_hashes(&mut self, hashed: &SourceIndex) {
+        let hashes: HashMap<&Path, &IndexedFile> = hashed
+            .files
+            .iter()
+            .map(|file| (file.reference.path.as_path(), file))
+            .collect();
+        for file in self
+            .files
+            .iter_mut()
+            .filter(|file| file.reference.hash.is_none())
+        {
+            let hash = hashes
+                .get(file.reference.path.as_path())
+                .filter(|other| other.size == file.size && other.modified == file.modified)
+                .and_then(|other| other.reference.hash);
+            file.reference.hash = hash;
+        }
+    }
+    /// Lists the images in the archive at `root`, which is the source folder.
+    /// An archive is only written as a whole, so its files are taken from the previous scan
+    /// as long as the archive keeps its modification time.
+    fn scan_archive(&mut self, root: &Path, walk: &mut Walk) -> ControlFlow<()> {
+        (walk.progress)(root, 0)?;
+        let Ok(metadata) = fs::metadata(root) else {
+            return ControlFlow::Continue(());
+        };
+        let Some(modified) = modified_nanos(&metadata) else {
+            return ControlFlow::Continue(());
+        };
+        self.directories.insert(root.to_path_buf(), modified);
+        let previous = walk.previous.as_ref();
+        if let Some(previous) = previous.filter(|previous| previous.is_unchanged(root, modified)) {
+            self.files.extend(previous.index.files.iter().cloned());
+            return ControlFlow::Continue(());
+        }
+        let options = walk.options;
+        let listed = archive::for_each_entry(root, |name, size, content| {
+            let dir = name.parent().unwrap_or(Path::new(""));
+            if !options.scans_size(size)
+                || !options.scans_depth(scan_options::depth_of_dir(dir))
+                || walk.filter.skips_path(name, false)
+            {
+                return ControlFlow::Continue(());
+            }
+            (walk.progress)(&root.join(dir), self.files.len())?;
+            let mut header = Vec::new();
+            if let Err(e) = content.take(decode::HEADER_LEN).read_to_end(&mut header) {
+                warn!("Skipping {name:?} in {root:?} with error {e:?}");
+                return ControlFlow::Continue(());
+            }
+            let format = RefFormat::from_header(&header);
+            if !format.is_some_and(|format| self.formats.contains(&format)) {
+                return ControlFlow::Continue(());
+            }
+            let reference = Reference {
+                path: root.join(name),
+                format,
+                sequence: Vec::new(),
+                modified: Some(modified / 1_000_000_000),
+                dimensions: decode::dimensions_from(Cursor::new(header)),
+                hash: None,
+            };
+            self.files.push(IndexedFile {
+                reference,
+                size: metadata.len(),
+                modified,
+            });
+            ControlFlow::Continue(())
+        });
+        listed.unwrap_or_else(|e| {
+            warn!("Could not list the archive {root:?} with error {e:?}");
+            ControlFlow::Continue(())
+        })
+    }
+    /// Scans `dir`, which is `depth` directories below the source folder.
+    fn scan_dir(&mut self, dir: &Path, depth: usize, walk: &mut Walk) -> ControlFlow<()> {
+        (walk.progress)(dir, self.files.len())?;
+        let Some(modified) = fs::metadata(dir).ok().and_then(|m| modified_nanos(&m)) else {
+            return ControlFlow::Continue(());
+        };
+        self.directories.insert(dir.to_path_buf(), modified);
+        let previous = walk.previous.as_ref();
+        if let Some(previous) = previous.filter(|previous| previous.is_unchanged(dir, modified)) {
+            self.files
+                .extend(previous.files_in(dir).map(|file| (*file).clone()));
+            let subdirectories: Vec<PathBuf> = previous
+                .subdirectories(dir)
+                .map(Path::to_path_buf)
+                .collect();
+            for subdirectory in subdirectories {
+                self.scan_dir(&subdirectory, depth + 1, walk)?;
+            }
+            return ControlFlow::Continue(());
+        }
+        let Ok(entries) = fs::read_dir(dir) else {
+            return ControlFlow::Continue(());
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect();
+        paths.sort_unstable();
+        let options = walk.options;
+        for path in paths {
+            let is_link = fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.is_symlink());
+            if is_link && !options.follow_links {
+                continue;
+            }
+            // follows links, broken links are skipped
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let relative = path.strip_prefix(walk.root).unwrap_or(&path);
+            if walk.filter.skips(relative, metadata.is_dir()) {
+                continue;
+            }
+            if metadata.is_dir() {
+                if options.scans_depth(depth + 1) && !(is_link && is_link_loop(dir, &path)) {
+                    self.scan_dir(&path, depth + 1, walk)?;
+                }
+                continue;
+            }
+            let size = metadata.len();
+            let Some(modified) = modified_nanos(&metadata).filter(|_| options.scans_size(size))
+            else {
+                continue;
+            };
+            let indexed = walk
+                .previous
+                .as_ref()
+                .and_then(|previous| previous.file(&path))
+                .filter(|file| file.size == size && file.modified == modified);
+            if let Some(file) = indexed {
+                self.files.push(file.clone());
+            } else if let Some(reference) = Reference::read(path, &self.formats) {
+                self.files.push(IndexedFile {
+                    reference,
+                    size,
+                    modified,
+                });
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// State of a running scan.
+struct Walk<'a, 'p> {
+    /// The source folder.
+    root: &'a Path,
+    options: &'a ScanOptions,
+    filter: ScanFilter,
+    previous: Option<Lookup<'a>>,
+    progress: &'p mut dyn FnMut(&Path, usize) -> ControlFlow<()>,
+}
+
+/// Lookups into an index of a previous scan.
+struct Lookup<'a> {
+    index: &'a SourceIndex,
+    files: HashMap<&'a Path, &'a IndexedFile>,
+    files_by_dir: HashMap<&'a Path, Vec<&'a IndexedFile>>,
+    subdirectories: HashMap<&'a Path, Vec<&'a Path>>,
+}
+impl<'a> Lookup<'a> {
+    fn new(index: &'a SourceIndex) -> Lookup<'a> {
+        let mut files = HashMap::new();
+        let mut files_by_dir: HashMap<&Path, Vec<&IndexedFile>> = HashMap::new();
+        for file in &index.files {
+            let path = file.reference.path.as_path();
+            files.insert(path, file);
+            if let Some(parent) = path.parent() {
+                files_by_dir.entry(parent).or_default().push(file);
+            }
+        }
+        let mut subdirectories: HashMap<&Path, Vec<&Path>> = HashMap::new();
+        for dir in index.directories.keys() {
+            if let Some(parent) = dir.parent() {
+                subdirectories.entry(parent).or_default().push(dir);
+            }
+        }
+        for dirs in subdirectories.values_mut() {
+            dirs.sort_unstable();
+        }
+        Lookup {
+            index,
+            files,
+            files_by_dir,
+            subdirectories,
+        }
+    }
+    fn is_unchanged(&self, dir: &Path, modified: u64) -> bool {
+        self.index.directories.get(dir) == Some(&modified)
+    }
+    fn file(&self, path: &Path) -> Option<&'a IndexedFile> {
+        self.files.get(path).copied()
+    }
+    fn files_in(&self, dir: &Path) -> impl Iterator<Item = &'a IndexedFile> + '_ {
+        self.files_by_dir.get(dir).into_iter().flatten().copied()
+    }
+    fn subdirectories(&self, dir: &Path) -> impl Iterator<Item = &'a Path> + '_ {
+        self.subdirectories.get(dir).into_iter().flatten().copied()
+    }
+}
+
+/// Computes the content hash of the file at `path` if it still has the indexed `size` and
+/// `modified` time. Files that changed since they were scanned are left for the next scan.
+/// Unchanged files are read in full.
+pub fn hash_unchanged(path: &Path, size: u64, modified: u64) -> Option<HashedFile> {
+    let unchanged = archive::container_metadata(path).is_ok_and(|metadata| {
+        metadata.len() == size && modified_nanos(&metadata) == Some(modified)
+    });
+    if !unchanged {
+        return None;
+    }
+    match content_hash::hash_file(path) {
+        Ok(hash) => Some(HashedFile {
+            path: path.to_path_buf(),
+            size,
+            modified,
+            hash,
+        }),
+        Err(e) => {
+            warn!("Could not hash {path:?} with error {e:?}");
+            None
+        }
+    }
+}
+
+fn modified_nanos(metadata: &fs::Metadata) -> Option<u64> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(modified.as_nanos()).ok()
+}
+
+/// Whether the link at `path` in `dir` points to `dir` or one of its parents,
+/// which would be scanned forever.
+fn is_link_loop(dir: &Path, path: &Path) -> bool {
+    match (fs::canonicalize(dir), fs::canonicalize(path)) {
+        (Ok(dir), Ok(target)) => dir.starts_with(target),
+        _ => true,
+    }
+}
+
+/// Whether a drive is mounted at `dir`, which then lies on another device than its parent.
+#[cfg(unix)]
+fn is_mount_point(dir: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let Ok(dir) = fs::canonicalize(dir) else {
+        return false;
+    };
+    let Some(parent) = dir.parent() else {
+        return true;
+    };
+    match (fs::metadata(&dir), fs::metadata(parent)) {
+        (Ok(dir), Ok(parent)) => dir.dev() != parent.dev(),
+        _ => false,
+    }
+}
+#[cfg(not(unix))]
+fn is_mount_point(_dir: &Path) -> bool {
+    false
+}
@@ -1,14 +1,31 @@
 // SPDX-License-Identifier: {{LICENSE}}
 
+mod album;
+mod animation;
 mod app;
+mod archive;
+mod browser;
+mod collection;
 mod config;
+mod content_hash;
+mod decode;
+mod duplicates;
+mod export;
 mod figure_drawing;
 mod i18n;
+mod index;
 mod io;
 mod log;
+mod metadata;
+mod query;
 mod reference;
 mod reference_board;
+mod scan;
+mod scan_options;
+mod similarity;
+mod thumbnail;
 mod view;
+mod watch;
 
 fn main() -> cosmic::iced::Result {
     // start logging
@@ -0,0 +1,77 @@
+//! User provided information about single references.
+
+use std::collections::BTreeSet;
+
+use crate::reference::{ContentRating, SourceFolder};
+
+/// Tags, rating, favorite flag and notes of a reference.
+///
+/// Stored in [`crate::reference::RefStore::metadata`] keyed by the reference path,
+/// so it survives rescans of the source folders.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct ReferenceMeta {
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    /// Stars from 1 to 5, `None` if not rated.
+    #[serde(default)]
+    pub rating: Option<u8>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub notes: String,
+    /// Hidden references are never sampled, but stay visible in the browser to be restored.
+    #[serde(default)]
+    pub hidden: bool,
+    /// Takes precedence over [`SourceFolder::rating`] of the folder containing the reference.
+    #[serde(default)]
+    pub content_override: Option<ContentRating>,
+}
+impl ReferenceMeta {
+    pub const MAX_RATING: u8 = 5;
+    /// Whether nothing was set, in which case the entry does not need to be stored.
+    pub fn is_empty(&self) -> bool {
+        self == &ReferenceMeta::default()
+    }
+    pub fn apply(&mut self, edit: MetadataEdit) {
+        match edit {
+            MetadataEdit::AddTag(tag) => {
+                let tag = tag.trim();
+                if !tag.is_empty() {
+                    self.tags.insert(tag.to_lowercase());
+                }
+            }
+            MetadataEdit::RemoveTag(tag) => {
+                self.tags.remove(&tag);
+            }
+            // choosing the current rating again clears it
+            MetadataEdit::SetRating(rating) if self.rating == Some(rating) => self.rating = None,
+            MetadataEdit::SetRating(rating) => {
+                self.rating = Some(rating.clamp(1, Self::MAX_RATING));
+            }
+            MetadataEdit::SetFavorite(favorite) => self.favorite = favorite,
+            MetadataEdit::SetNotes(notes) => self.notes = notes,
+            MetadataEdit::SetHidden(hidden) => self.hidden = hidden,
+            MetadataEdit::SetContentOverride(content_override) => {
+                self.content_override = content_override;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MetadataEdit {
+    AddTag(String),
+    RemoveTag(String),
+    SetRating(u8),
+    SetFavorite(bool),
+    SetNotes(String),
+    SetHidden(bool),
+    /// `None` falls back to the rating of the folder.
+    SetContentOverride(Option<ContentRating>),
+}
+
+/// Content rating of a reference in `source` with `meta`.
+pub fn content_rating(meta: Option<&ReferenceMeta>, source: &SourceFolder) -> ContentRating {
+    meta.and_then(|meta| meta.content_override)
+        .unwrap_or(source.rating)
+}
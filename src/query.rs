@@ -0,0 +1,707 @@
+//! Query language to filter references by their metadata, source and format.
+//!
+//! A query is a list of terms which all have to match, e.g.
+//! `pose:standing AND -clothed rating>=3 source:"gesture"`.
+//!
+//! - `word` matches references tagged with `word`. Unknown keys like `pose:standing` are tags as well.
+//! - `tag:word` matches a tag explicitly, e.g. for tags named like a keyword.
+//! - `source:text` and `path:text` match if the source folder or reference path contains `text`.
+//! - `format:png` matches the detected file format.
+//! - `rating>=3` compares the star rating with `:`, `=`, `<`, `<=`, `>` or `>=`. Unrated references count as 0.
+//! - `width>=2000` and `height<1000` compare the image size in pixels.
+//! - `modified>=2024-01-31` compares the day the file was last modified.
+//! - `content<=artistic` compares the content rating, which is `general`, `artistic` or `explicit`.
+//! - `is:favorite`, `is:sfw` (same as `content:general`), `is:sequence`, `is:rated` and `is:hidden` match flags of the reference.
+//! - Terms are combined with `AND` (also implied by a space), `OR` and parentheses,
+//!   and negated with a leading `-` or `NOT`.
+//!
+//! Values containing spaces are quoted with `"`. Keys and tags are case insensitive.
+
+use std::fmt;
+
+use crate::{
+    metadata::{self, ReferenceMeta},
+    reference::{ContentRating, RefFormat, Reference, SourceFolder},
+};
+
+/// A parsed query. The default query matches every reference.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    expr: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Tag(String),
+    Source(String),
+    Path(String),
+    Format(RefFormat),
+    Rating(Comparison, u8),
+    Width(Comparison, u32),
+    Height(Comparison, u32),
+    /// Days since the unix epoch.
+    Modified(Comparison, u64),
+    Favorite,
+    Content(Comparison, ContentRating),
+    Sequence,
+    Rated,
+    Hidden,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+impl Comparison {
+    fn holds<T: Ord>(self, lhs: T, rhs: T) -> bool {
+        match self {
+            Comparison::Eq => lhs == rhs,
+            Comparison::Lt => lhs < rhs,
+            Comparison::Le => lhs <= rhs,
+            Comparison::Gt => lhs > rhs,
+            Comparison::Ge => lhs >= rhs,
+        }
+    }
+}
+
+/// Why a query could not be parsed. `position` is the char offset in the query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    pub position: usize,
+    pub kind: QueryErrorKind,
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryErrorKind {
+    UnclosedQuote,
+    UnclosedParenthesis,
+    UnexpectedParenthesis,
+    /// An operator like `AND` or `-` without a term after it.
+    MissingTerm,
+    EmptyValue(String),
+    UnknownFormat(String),
+    UnknownFlag(String),
+    UnknownContentRating(String),
+    InvalidRating(String),
+    InvalidNumber(String),
+    /// Dates are expected as `YYYY-MM-DD`.
+    InvalidDate(String),
+    /// A comparison like `<` used with a key other than `rating`.
+    UnsupportedComparison(String),
+    /// More than [`MAX_DEPTH`] parentheses or negations are nested.
+    TooDeep,
+}
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            QueryErrorKind::UnclosedQuote => write!(f, "unclosed quote"),
+            QueryErrorKind::UnclosedParenthesis => write!(f, "unclosed parenthesis"),
+            QueryErrorKind::UnexpectedParenthesis => write!(f, "unexpected `)`"),
+            QueryErrorKind::MissingTerm => write!(f, "expected a term"),
+            QueryErrorKind::EmptyValue(key) => write!(f, "`{key}` needs a value"),
+            QueryErrorKind::UnknownFormat(format) => write!(
+                f,
+                "unknown format `{format}`, expected one of {}",
+                RefFormat::ALL.map(RefFormat::name).join(", ")
+            ),
+            QueryErrorKind::UnknownFlag(flag) => write!(
+                f,
+                "unknown flag `is:{flag}`, expected favorite, sfw, sequence, rated or hidden"
+            ),
+            QueryErrorKind::UnknownContentRating(rating) => write!(
+                f,
+                "unknown content rating `{rating}`, expected one of {}",
+                ContentRating::ALL.map(ContentRating::name).join(", ")
+            ),
+            QueryErrorKind::InvalidRating(rating) => write!(
+                f,
+                "invalid rating `{rating}`, expected a number from 0 to {}",
+                ReferenceMeta::MAX_RATING
+            ),
+            QueryErrorKind::InvalidNumber(number) => {
+                write!(f, "invalid number `{number}`")
+            }
+            QueryErrorKind::InvalidDate(date) => {
+                write!(f, "invalid date `{date}`, expected YYYY-MM-DD")
+            }
+            QueryErrorKind::UnsupportedComparison(key) => {
+                write!(f, "`{key}` can only be used with `:`")
+            }
+            QueryErrorKind::TooDeep => {
+                write!(
+                    f,
+                    "more than {MAX_DEPTH} parentheses or negations are nested"
+                )
+            }
+        }?;
+        write!(f, " at position {}", self.position + 1)
+    }
+}
+impl std::error::Error for QueryError {}
+
+impl Query {
+    /// Query matching references matched by both `self` and `other`.
+    pub fn and(self, other: Query) -> Query {
+        let expr = match (self.expr, other.expr) {
+            (Some(lhs), Some(rhs)) => Some(Expr::And(Box::new(lhs), Box::new(rhs))),
+            (lhs, rhs) => lhs.or(rhs),
+        };
+        Query { expr }
+    }
+    pub fn parse(input: &str) -> Result<Query, QueryError> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens,
+            next: 0,
+            end: input.chars().count(),
+            depth: 0,
+        };
+        if parser.peek().is_none() {
+            return Ok(Query::default());
+        }
+        let expr = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(Query { expr: Some(expr) }),
+            Some(token) => Err(QueryError {
+                position: token.position,
+                kind: QueryErrorKind::UnexpectedParenthesis,
+            }),
+        }
+    }
+    /// Whether the query matches everything.
+    pub fn is_empty(&self) -> bool {
+        self.expr.is_none()
+    }
+    pub fn matches(
+        &self,
+        reference: &Reference,
+        source: &SourceFolder,
+        meta: Option<&ReferenceMeta>,
+    ) -> bool {
+        self.expr
+            .as_ref()
+            .is_none_or(|expr| expr.matches(reference, source, meta))
+    }
+}
+
+impl Expr {
+    fn matches(
+        &self,
+        reference: &Reference,
+        source: &SourceFolder,
+        meta: Option<&ReferenceMeta>,
+    ) -> bool {
+        match self {
+            Expr::And(lhs, rhs) => {
+                lhs.matches(reference, source, meta) && rhs.matches(reference, source, meta)
+            }
+            Expr::Or(lhs, rhs) => {
+                lhs.matches(reference, source, meta) || rhs.matches(reference, source, meta)
+            }
+            Expr::Not(expr) => !expr.matches(reference, source, meta),
+            Expr::Tag(tag) => meta.is_some_and(|meta| meta.tags.contains(tag)),
+            Expr::Source(text) => contains_ignore_case(&source.path.to_string_lossy(), text),
+            Expr::Path(text) => contains_ignore_case(&reference.path.to_string_lossy(), text),
+            Expr::Format(format) => reference.format == Some(*format),
+            Expr::Rating(comparison, rating) => {
+                let actual = meta.and_then(|meta| meta.rating).unwrap_or(0);
+                comparison.holds(actual, *rating)
+            }
+            Expr::Width(comparison, width) => reference
+                .dimensions
+                .is_some_and(|(actual, _)| comparison.holds(actual, *width)),
+            Expr::Height(comparison, height) => reference
+                .dimensions
+                .is_some_and(|(_, actual)| comparison.holds(actual, *height)),
+            Expr::Modified(comparison, day) => reference
+                .modified
+                .is_some_and(|modified| comparison.holds(modified / SECONDS_PER_DAY, *day)),
+            Expr::Favorite => meta.is_some_and(|meta| meta.favorite),
+            Expr::Content(comparison, rating) => {
+                comparison.holds(metadata::content_rating(meta, source), *rating)
+            }
+            Expr::Sequence => reference.is_sequence(),
+            Expr::Rated => meta.is_some_and(|meta| meta.rating.is_some()),
+            Expr::Hidden => meta.is_some_and(|meta| meta.hidden),
+        }
+    }
+}
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+/// Parentheses and negations may be nested this deep, so parsing and matching can not overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+/// `needle` is expected to be lowercase already.
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(needle)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    position: usize,
+    kind: TokenKind,
+}
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    OpenParen,
+    CloseParen,
+    And,
+    Or,
+    Not,
+    Term {
+        key: Option<String>,
+        comparison: Option<Comparison>,
+        value: String,
+    },
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().enumerate().peekable();
+    while let Some(&(position, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token {
+                    position,
+                    kind: TokenKind::OpenParen,
+                });
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token {
+                    position,
+                    kind: TokenKind::CloseParen,
+                });
+            }
+            '-' => {
+                chars.next();
+                tokens.push(Token {
+                    position,
+                    kind: TokenKind::Not,
+                });
+            }
+            _ => {
+                let mut key = None;
+                let mut comparison = None;
+                let mut value = String::new();
+                let mut quoted = false;
+                while let Some(&(quote_position, c)) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    chars.next();
+                    if c == '"' {
+                        quoted = true;
+                        loop {
+                            match chars.next() {
+                                Some((_, '"')) => break,
+                                Some((_, c)) => value.push(c),
+                                None => {
+                                    return Err(QueryError {
+                                        position: quote_position,
+                                        kind: QueryErrorKind::UnclosedQuote,
+                                    })
+                                }
+                            }
+                        }
+                    } else if key.is_none() && !quoted && !value.is_empty() && is_operator(c) {
+                        comparison = Some(match (c, chars.peek()) {
+                            (':' | '=', _) => Comparison::Eq,
+                            ('<', Some((_, '='))) => {
+                                chars.next();
+                                Comparison::Le
+                            }
+                            ('>', Some((_, '='))) => {
+                                chars.next();
+                                Comparison::Ge
+                            }
+                            ('<', _) => Comparison::Lt,
+                            _ => Comparison::Gt,
+                        });
+                        key = Some(std::mem::take(&mut value).to_lowercase());
+                    } else {
+                        value.push(c);
+                    }
+                }
+                let kind = match (key, quoted, value.as_str()) {
+                    (None, false, "AND") => TokenKind::And,
+                    (None, false, "OR") => TokenKind::Or,
+                    (None, false, "NOT") => TokenKind::Not,
+                    (key, _, _) => TokenKind::Term {
+                        key,
+                        comparison,
+                        value,
+                    },
+                };
+                tokens.push(Token { position, kind });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_operator(c: char) -> bool {
+    matches!(c, ':' | '=' | '<' | '>')
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    /// Char count of the input, used as position of errors at the end.
+    end: usize,
+    /// Parentheses and negations around the next token.
+    depth: usize,
+}
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+    fn missing_term(&self) -> QueryError {
+        QueryError {
+            position: self.peek().map_or(self.end, |token| token.position),
+            kind: QueryErrorKind::MissingTerm,
+        }
+    }
+    fn parse_or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.parse_and()?;
+        while self.peek().is_some_and(|token| token.kind == TokenKind::Or) {
+            self.next += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+    fn parse_and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            match self.peek().map(|token| &token.kind) {
+                Some(TokenKind::And) => self.next += 1,
+                // terms next to each other are implicitly joined with `AND`
+                Some(TokenKind::Not | TokenKind::OpenParen | TokenKind::Term { .. }) => {}
+                _ => return Ok(expr),
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+    }
+    /// Parses the term after a parenthesis or negation at `position` with `parse`.
+    fn parse_nested(
+        &mut self,
+        position: usize,
+        parse: impl FnOnce(&mut Self) -> Result<Expr, QueryError>,
+    ) -> Result<Expr, QueryError> {
+        if self.depth == MAX_DEPTH {
+            return Err(QueryError {
+                position,
+                kind: QueryErrorKind::TooDeep,
+            });
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+    fn parse_unary(&mut self) -> Result<Expr, QueryError> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(self.missing_term());
+        };
+        self.next += 1;
+        match token.kind {
+            TokenKind::Not => {
+                let expr = self.parse_nested(token.position, Self::parse_unary)?;
+                Ok(Expr::Not(Box::new(expr)))
+            }
+            TokenKind::OpenParen => {
+                let expr = self.parse_nested(token.position, Self::parse_or)?;
+                match self.peek() {
+                    Some(Token {
+                        kind: TokenKind::CloseParen,
+                        ..
+                    }) => {
+                        self.next += 1;
+                        Ok(expr)
+                    }
+                    _ => Err(QueryError {
+                        position: token.position,
+                        kind: QueryErrorKind::UnclosedParenthesis,
+                    }),
+                }
+            }
+            TokenKind::Term {
+                key,
+                comparison,
+                value,
+            } => parse_term(key, comparison, value).map_err(|kind| QueryError {
+                position: token.position,
+                kind,
+            }),
+            TokenKind::CloseParen => Err(QueryError {
+                position: token.position,
+                kind: QueryErrorKind::UnexpectedParenthesis,
+            }),
+            TokenKind::And | TokenKind::Or => {
+                self.next -= 1;
+                Err(self.missing_term())
+            }
+        }
+    }
+}
+
+fn parse_term(
+    key: Option<String>,
+    comparison: Option<Comparison>,
+    value: String,
+) -> Result<Expr, QueryErrorKind> {
+    let value = value.to_lowercase();
+    let Some(key) = key else {
+        return Ok(Expr::Tag(value));
+    };
+    if value.is_empty() {
+        return Err(QueryErrorKind::EmptyValue(key));
+    }
+    let comparison = comparison.unwrap_or(Comparison::Eq);
+    let is_ordered = matches!(
+        key.as_str(),
+        "rating" | "width" | "height" | "modified" | "content"
+    );
+    if !is_ordered && comparison != Comparison::Eq {
+        return Err(QueryErrorKind::UnsupportedComparison(key));
+    }
+    match key.as_str() {
+        "tag" => Ok(Expr::Tag(value)),
+        "source" => Ok(Expr::Source(value)),
+        "path" => Ok(Expr::Path(value)),
+        "format" => RefFormat::ALL
+            .into_iter()
+            .find(|format| format.name() == value)
+            .map(Expr::Format)
+            .ok_or(QueryErrorKind::UnknownFormat(value)),
+        "rating" => match value.parse::<u8>() {
+            Ok(rating) if rating <= ReferenceMeta::MAX_RATING => {
+                Ok(Expr::Rating(comparison, rating))
+            }
+            _ => Err(QueryErrorKind::InvalidRating(value)),
+        },
+        "width" | "height" => {
+            let Ok(pixels) = value.parse::<u32>() else {
+                return Err(QueryErrorKind::InvalidNumber(value));
+            };
+            Ok(if key == "width" {
+                Expr::Width(comparison, pixels)
+            } else {
+                Expr::Height(comparison, pixels)
+            })
+        }
+        "modified" => parse_date(&value)
+            .map(|day| Expr::Modified(comparison, day))
+            .ok_or(QueryErrorKind::InvalidDate(value)),
+        "content" => ContentRating::ALL
+            .into_iter()
+            .find(|rating| rating.name() == value)
+            .map(|rating| Expr::Content(comparison, rating))
+            .ok_or(QueryErrorKind::UnknownContentRating(value)),
+        "is" => match value.as_str() {
+            "favorite" => Ok(Expr::Favorite),
+            "sfw" => Ok(Expr::Content(Comparison::Eq, ContentRating::General)),
+            "sequence" => Ok(Expr::Sequence),
+            "rated" => Ok(Expr::Rated),
+            "hidden" => Ok(Expr::Hidden),
+            _ => Err(QueryErrorKind::UnknownFlag(value)),
+        },
+        // namespaced tags like `pose:standing`
+        _ => Ok(Expr::Tag(format!("{key}:{value}"))),
+    }
+}
+
+/// Parses a `YYYY-MM-DD` date into days since the unix epoch.
+fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // days from civil, see https://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    u64::try_from(era * 146097 + day_of_era - 719468).ok()
+}
+
+/// Text of a query input together with the result of parsing it.
+#[derive(Debug, Clone, Default)]
+pub struct QueryInput {
+    pub text: String,
+    /// Last query that parsed successfully. Kept while the text is invalid.
+    pub query: Query,
+    pub error: Option<QueryError>,
+}
+impl QueryInput {
+    pub fn set_text(&mut self, text: String) {
+        match Query::parse(&text) {
+            Ok(query) => {
+                self.query = query;
+                self.error = None;
+            }
+            Err(error) => self.error = Some(error),
+        }
+        self.text = text;
+    }
+}
+
+/// The view whose query input changed.
+#[derive(Debug, Clone, Copy)]
+pub enum QueryTarget {
+    FigureDrawing,
+    Browser,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Option<Expr> {
+        Query::parse(input).expect("query is valid").expr
+    }
+    fn error(input: &str) -> QueryError {
+        Query::parse(input).expect_err("query is invalid")
+    }
+    fn tag(tag: &str) -> Box<Expr> {
+        Box::new(Expr::Tag(tag.to_string()))
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("   "), None);
+    }
+
+    #[test]
+    fn and_binds_stronger_than_or() {
+        assert_eq!(
+            parse("a OR b c"),
+            Some(Expr::Or(tag("a"), Box::new(Expr::And(tag("b"), tag("c")))))
+        );
+        assert_eq!(
+            parse("a AND b OR c"),
+            Some(Expr::Or(Box::new(Expr::And(tag("a"), tag("b"))), tag("c")))
+        );
+    }
+
+    #[test]
+    fn negation_binds_to_the_next_term() {
+        assert_eq!(
+            parse("-a b"),
+            Some(Expr::And(Box::new(Expr::Not(tag("a"))), tag("b")))
+        );
+        assert_eq!(
+            parse("NOT (a OR b)"),
+            Some(Expr::Not(Box::new(Expr::Or(tag("a"), tag("b")))))
+        );
+    }
+
+    #[test]
+    fn parentheses_group_terms() {
+        assert_eq!(
+            parse("(a OR b) c"),
+            Some(Expr::And(Box::new(Expr::Or(tag("a"), tag("b"))), tag("c")))
+        );
+    }
+
+    #[test]
+    fn quoted_values_keep_spaces_and_are_no_keywords() {
+        assert_eq!(
+            parse("tag:\"Two Words\""),
+            Some(Expr::Tag("two words".into()))
+        );
+        assert_eq!(
+            parse("source:\"My Folder\""),
+            Some(Expr::Source("my folder".into()))
+        );
+        assert_eq!(parse("\"AND\""), Some(Expr::Tag("and".into())));
+    }
+
+    #[test]
+    fn keys_select_the_expression() {
+        assert_eq!(
+            parse("pose:Standing"),
+            Some(Expr::Tag("pose:standing".into()))
+        );
+        assert_eq!(parse("format:png"), Some(Expr::Format(RefFormat::Png)));
+        assert_eq!(parse("rating>=3"), Some(Expr::Rating(Comparison::Ge, 3)));
+        assert_eq!(parse("width<1000"), Some(Expr::Width(Comparison::Lt, 1000)));
+        assert_eq!(
+            parse("modified:2024-01-31"),
+            Some(Expr::Modified(Comparison::Eq, 19753))
+        );
+        assert_eq!(
+            parse("content<=artistic"),
+            Some(Expr::Content(Comparison::Le, ContentRating::ArtisticNudity))
+        );
+        assert_eq!(parse("is:favorite"), Some(Expr::Favorite));
+    }
+
+    #[test]
+    fn errors_report_kind_and_position() {
+        assert_eq!(
+            error("a \"open"),
+            QueryError {
+                position: 2,
+                kind: QueryErrorKind::UnclosedQuote
+            }
+        );
+        assert_eq!(error("(a OR b").kind, QueryErrorKind::UnclosedParenthesis);
+        assert_eq!(error("a)").kind, QueryErrorKind::UnexpectedParenthesis);
+        assert_eq!(
+            error("a AND"),
+            QueryError {
+                position: 5,
+                kind: QueryErrorKind::MissingTerm
+            }
+        );
+        assert_eq!(
+            error("rating>6").kind,
+            QueryErrorKind::InvalidRating("6".into())
+        );
+        assert_eq!(
+            error("source<3").kind,
+            QueryErrorKind::UnsupportedComparison("source".into())
+        );
+        assert_eq!(
+            error("is:foo").kind,
+            QueryErrorKind::UnknownFlag("foo".into())
+        );
+        assert_eq!(
+            error("modified:2024-13-01").kind,
+            QueryErrorKind::InvalidDate("2024-13-01".into())
+        );
+        assert_eq!(error("tag:").kind, QueryErrorKind::EmptyValue("tag".into()));
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let nested = |depth: usize| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Query::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(error(&nested(100_000)).kind, QueryErrorKind::TooDeep);
+        assert_eq!(error(&"-".repeat(100_000)).kind, QueryErrorKind::TooDeep);
+    }
+
+    #[test]
+    fn dates_count_days_since_the_unix_epoch() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2000-03-01"), Some(11017));
+        assert_eq!(parse_date("1969-12-31"), None);
+        assert_eq!(parse_date("2024-02-x"), None);
+    }
+}
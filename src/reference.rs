@@ -1,113 +1,895 @@
 use std::{
-    collections::HashMap,
-    ffi::OsStr,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    io::Cursor,
     path::{Path, PathBuf},
+    time::UNIX_EPOCH,
 };
 
-use crate::io;
+use image::ImageFormat;
+
+use crate::{
+    animation::LoadedReference,
+    archive,
+    collection::{SmartCollection, SortOrder},
+    content_hash::ContentHash,
+    decode,
+    duplicates::DuplicateGroups,
+    index::{self, SourceIndex},
+    io,
+    metadata::{self, MetadataEdit, ReferenceMeta},
+    query::Query,
+    scan_options::ScanOptions,
+    watch::SourceChanges,
+};
 
 pub const REF_STORE_FILENAME: &str = "refstore.ron";
+/// Upper bound for the memory used by decoded images in [`RefStore::ref_data`],
+/// enforced when decoded images are evicted. Only the current reference may exceed it on its own.
+pub const IMAGE_CACHE_BUDGET: usize = 1024 * 1024 * 1024;
+/// A single animated reference may only use this share of [`IMAGE_CACHE_BUDGET`],
+/// so the history window of the figure drawing page and the reference board still fit.
+pub const ANIMATION_BUDGET: usize = IMAGE_CACHE_BUDGET / 8;
 #[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
 pub struct RefStore {
     pub source_folders: Vec<SourceFolder>,
+    /// Tags, ratings and notes keyed by [`Reference::path`].
+    /// Kept separate from the scanned children, so rescans do not lose them.
+    #[serde(default)]
+    pub metadata: HashMap<PathBuf, ReferenceMeta>,
+    #[serde(default)]
+    pub collections: Vec<SmartCollection>,
+    /// Near-duplicates found in the background, sampled as a single reference.
+    #[serde(skip)]
+    pub duplicates: DuplicateGroups,
     #[serde(skip)]
     /// Deserialized images.
     /// Keys are `Self::references`
-    pub ref_data: HashMap<PathBuf, image::RgbaImage>,
+    pub ref_data: HashMap<PathBuf, LoadedReference>,
 }
 impl RefStore {
-    pub fn reference_count(&self, sfw_filter: bool) -> usize {
+    /// All references that can be sampled, which are those that are not hidden,
+    /// are rated at most `max_rating` and match `query`.
+    /// Of each group of duplicates only the first reference that can be sampled is included.
+    pub fn filtered<'a: 'q, 'q>(
+        &'a self,
+        max_rating: ContentRating,
+        query: &'q Query,
+    ) -> impl Iterator<Item = &'a Reference> + 'q {
+        let mut sampled_groups = HashSet::new();
+        self.source_folders
+            .iter()
+            .flat_map(move |source| {
+                source
+                    .children
+                    .iter()
+                    .filter(move |reference| self.can_sample(source, reference, max_rating, query))
+            })
+            .filter(move |reference| self.is_first_of_group(reference, &mut sampled_groups))
+    }
+    /// References at `paths` that can be sampled, in the order of `paths`.
+    /// Paths which are not part of any source folder are skipped.
+    pub fn filtered_paths<'a: 'q, 'q>(
+        &'a self,
+        paths: &'q [PathBuf],
+        max_rating: ContentRating,
+        query: &'q Query,
+    ) -> impl Iterator<Item = &'a Reference> + 'q {
+        let mut sampled_groups = HashSet::new();
+        // looked up once, as searching the whole store for every path is slow for large albums
+        let wanted: HashSet<&Path> = paths.iter().map(PathBuf::as_path).collect();
+        let found: HashMap<&Path, (&SourceFolder, &Reference)> = self
+            .source_folders
+            .iter()
+            .flat_map(|source| {
+                source
+                    .children
+                    .iter()
+                    .map(move |reference| (source, reference))
+            })
+            .filter(|(_, reference)| wanted.contains(reference.path.as_path()))
+            .map(|(source, reference)| (reference.path.as_path(), (source, reference)))
+            .collect();
+        paths
+            .iter()
+            .filter_map(move |path| found.get(path.as_path()).copied())
+            .filter(move |(source, reference)| {
+                self.can_sample(source, reference, max_rating, query)
+            })
+            .map(|(_, reference)| reference)
+            .filter(move |reference| self.is_first_of_group(reference, &mut sampled_groups))
+    }
+    /// Whether no other reference of the duplicate group of `reference` is in `sampled_groups` yet.
+    fn is_first_of_group(
+        &self,
+        reference: &Reference,
+        sampled_groups: &mut HashSet<usize>,
+    ) -> bool {
+        match self.duplicates.group_of(&reference.path) {
+            Some(group) => sampled_groups.insert(group),
+            None => true,
+        }
+    }
+    fn can_sample(
+        &self,
+        source: &SourceFolder,
+        reference: &Reference,
+        max_rating: ContentRating,
+        query: &Query,
+    ) -> bool {
+        let meta = self.metadata.get(&reference.path);
+        !source.offline
+            && metadata::content_rating(meta, source) <= max_rating
+            && !meta.is_some_and(|meta| meta.hidden)
+            && query.matches(reference, source, meta)
+    }
+    pub fn is_hidden(&self, path: &Path) -> bool {
+        self.metadata.get(path).is_some_and(|meta| meta.hidden)
+    }
+    /// The reference at `path` together with the source folder containing it.
+    pub fn find(&self, path: &Path) -> Option<(&SourceFolder, &Reference)> {
+        self.source_folders.iter().find_map(|source| {
+            source
+                .children
+                .iter()
+                .find(|reference| reference.path == path)
+                .map(|reference| (source, reference))
+        })
+    }
+    /// Moves the metadata of moved references along with them and marks the changed paths in the
+    /// index of their source folders. Returns the source folders which have to be scanned again.
+    pub fn apply_changes(&mut self, changes: &SourceChanges) -> Vec<PathBuf> {
+        for (from, to) in &changes.renamed {
+            self.move_metadata(from, to, &[]);
+        }
         self.source_folders
+            .iter_mut()
+            .filter_map(|source| {
+                source
+                    .invalidate_paths(&changes.paths)
+                    .then(|| source.path.clone())
+            })
+            .collect()
+    }
+    /// Moves the metadata of all references below `from` to below `to`,
+    /// except the ones below the `nested` source folders, which stay where they are.
+    fn move_metadata(&mut self, from: &Path, to: &Path, nested: &[PathBuf]) {
+        let moved: Vec<PathBuf> = self
+            .metadata
+            .keys()
+            .filter(|path| {
+                path.starts_with(from) && !nested.iter().any(|source| path.starts_with(source))
+            })
+            .cloned()
+            .collect();
+        for path in moved {
+            if let (Some(meta), Some(new_path)) =
+                (self.metadata.remove(&path), rebase(&path, from, to))
+            {
+                self.metadata.insert(new_path, meta);
+            }
+        }
+    }
+    /// Paths of the source folders that lie inside the source folder at `path`, like `/lib/poses`
+    /// in `/lib`. Their references belong to them, so they stay when `path` is relocated.
+    pub fn nested_sources(&self, path: &Path) -> Vec<PathBuf> {
+        self.source_folders
+            .iter()
+            .filter(|source| source.path != path && source.path.starts_with(path))
+            .map(|source| source.path.clone())
+            .collect()
+    }
+    /// Changes the path of the source folder at `from` to `to`, e.g. after the library was copied
+    /// to another drive. Its index and the metadata of its references move along with it,
+    /// except the metadata of references in [nested sources](Self::nested_sources).
+    /// Returns `false` if there is no source at `from`.
+    pub fn relocate_source(&mut self, from: &Path, to: &Path) -> bool {
+        let nested = self.nested_sources(from);
+        let Some(source) = self.source_folders.iter_mut().find(|s| s.path == from) else {
+            return false;
+        };
+        source.path = to.to_path_buf();
+        source.index.rebase(from, to);
+        source.update_children();
+        self.move_metadata(from, to, &nested);
+        true
+    }
+    /// Reconnects files that vanished from the last scans to files with the same content hash,
+    /// which were moved or renamed while the app was not running. Their metadata moves along.
+    /// Returns the old and new paths, so other data keyed by path can follow.
+    ///
+    /// Vanished files that are not found are forgotten if `forget_unmatched` is set, which should
+    /// only be done once all sources are scanned and hashed.
+    pub fn reconnect_moved(&mut self, forget_unmatched: bool) -> Vec<(PathBuf, PathBuf)> {
+        let mut vanished = Vec::new();
+        for source in self.source_folders.iter_mut().filter(|s| !s.offline) {
+            vanished.extend(source.index.take_vanished());
+        }
+        let mut present: HashMap<ContentHash, &Path> = HashMap::new();
+        for (path, hash) in self
+            .source_folders
             .iter()
-            .filter(|source| if sfw_filter { source.is_sfw } else { true })
-            .map(|source| source.children.len())
-            .sum()
-    }
-    pub fn get_reference(&self, index: usize, sfw_filter: bool) -> Option<&Reference> {
-        let mut index = index;
-        for source in &self.source_folders {
-            if sfw_filter && !source.is_sfw {
-                continue;
+            .flat_map(|source| source.index.hashes())
+        {
+            present.entry(hash).or_insert(path);
+        }
+        let mut moved = Vec::new();
+        let mut unmatched = Vec::new();
+        for (path, hash) in vanished {
+            match present.get(&hash) {
+                // a copy that has its own metadata keeps it
+                Some(to) if !self.metadata.contains_key(*to) => {
+                    moved.push((path, to.to_path_buf()))
+                }
+                Some(_) => {}
+                None => unmatched.push((path, hash)),
             }
-            if index < source.children.len() {
-                return Some(&source.children[index]);
+        }
+        if !forget_unmatched {
+            for (path, hash) in unmatched {
+                if let Some(source) = self
+                    .source_folders
+                    .iter_mut()
+                    .find(|source| path.starts_with(&source.path))
+                {
+                    source.index.remember_vanished(path, hash);
+                }
             }
-            index = index - source.children.len();
         }
-        None
+        for (from, to) in &moved {
+            self.move_metadata(from, to, &[]);
+        }
+        moved
+    }
+    pub fn metadata(&self, path: &Path) -> Option<&ReferenceMeta> {
+        self.metadata.get(path)
+    }
+    /// Applies `edit` to the metadata of all `paths`. Saving the store is left to the caller.
+    pub fn apply_metadata_edit_bulk<'a>(
+        &mut self,
+        paths: impl IntoIterator<Item = &'a Path>,
+        edit: MetadataEdit,
+    ) {
+        for path in paths {
+            self.apply_metadata_edit(path, edit.clone());
+        }
     }
-    pub fn sync_with_source_folders(&mut self) {
-        for source in &mut self.source_folders {
-            source.children = recursive_get_imgs(&source.path, source.is_sfw).collect();
+    /// Applies `edit` to the metadata of `path`. Saving the store is left to the caller.
+    pub fn apply_metadata_edit(&mut self, path: &Path, edit: MetadataEdit) {
+        let meta = self.metadata.entry(path.to_path_buf()).or_default();
+        meta.apply(edit);
+        if meta.is_empty() {
+            self.metadata.remove(path);
         }
     }
+    pub fn collection(&self, id: u32) -> Option<&SmartCollection> {
+        self.collections
+            .iter()
+            .find(|collection| collection.id == id)
+    }
+    pub fn collection_mut(&mut self, id: u32) -> Option<&mut SmartCollection> {
+        self.collections
+            .iter_mut()
+            .find(|collection| collection.id == id)
+    }
+    /// Adds a new collection with `name` and `query` and returns its id.
+    pub fn add_collection(&mut self, name: String, query: String) -> u32 {
+        let id = self
+            .collections
+            .iter()
+            .map(|collection| collection.id + 1)
+            .max()
+            .unwrap_or_default();
+        self.collections.push(SmartCollection {
+            id,
+            name,
+            query,
+            sort: SortOrder::default(),
+        });
+        self.save_to_disk();
+        id
+    }
+    pub fn remove_collection(&mut self, id: u32) {
+        self.collections.retain(|collection| collection.id != id);
+        self.save_to_disk();
+    }
+    /// Loads the store together with the file index of its source folders,
+    /// so the references of the last scan are available before the folders are scanned again.
     pub fn try_load() -> Option<RefStore> {
-        io::try_load(REF_STORE_FILENAME)
+        let mut ref_store: RefStore = io::try_load(REF_STORE_FILENAME)?;
+        if let Some(mut indices) = index::try_load() {
+            for source in &mut ref_store.source_folders {
+                source.set_index(indices.remove(&source.path).unwrap_or_default());
+            }
+        }
+        Some(ref_store)
+    }
+    /// Saves the file index of the source folders, which is kept apart from the store,
+    /// so editing metadata does not write the whole index.
+    pub fn save_index(&self) -> Option<()> {
+        index::save_to_disk(&self.source_folders)
     }
     pub fn save_to_disk(&self) -> Option<()> {
         io::save_to_disk(self, REF_STORE_FILENAME)
     }
-    pub fn push_folders(&mut self, folders: &[impl AsRef<Path>], is_sfw: bool) {
-        for folder in folders {
-            self.push_folder(folder.as_ref(), is_sfw);
-        }
+    /// Adds `folders` which are not sources yet and returns their paths.
+    /// The new sources are empty until they are scanned.
+    pub fn push_folders(
+        &mut self,
+        folders: &[impl AsRef<Path>],
+        rating: ContentRating,
+    ) -> Vec<PathBuf> {
+        let added = folders
+            .iter()
+            .filter_map(|folder| self.push_folder(folder.as_ref(), rating))
+            .collect();
         self.save_to_disk();
+        added
     }
-    fn push_folder(&mut self, folder_to_add: &Path, is_sfw: bool) {
-        let mut source = SourceFolder {
+    fn push_folder(&mut self, folder_to_add: &Path, rating: ContentRating) -> Option<PathBuf> {
+        let source = SourceFolder {
             path: folder_to_add.to_path_buf(),
-            is_sfw,
+            rating,
+            formats: RefFormat::default_formats(),
+            detect_sequences: false,
+            scan_options: ScanOptions::default(),
+            index: SourceIndex::default(),
+            offline: false,
             children: Vec::new(),
         };
-        if !self.source_folders.contains(&source) {
-            source.children = recursive_get_imgs(&source.path, false).collect();
-            self.source_folders.push(source);
-            tracing::info!("Pushed source folder {folder_to_add:?} successfully ");
+        if self.source_folders.contains(&source) {
+            return None;
         }
+        self.source_folders.push(source);
+        tracing::info!("Pushed source folder {folder_to_add:?} successfully ");
+        Some(folder_to_add.to_path_buf())
     }
 }
 
-#[derive(Eq, Hash, Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Eq, Debug, Default, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Reference {
+    /// Path of the image. For sequences this is the first frame.
     pub path: PathBuf,
+    /// Format detected from the file content when the reference was scanned.
+    #[serde(default)]
+    pub format: Option<RefFormat>,
+    /// All frames in order if the reference is a numbered image sequence.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sequence: Vec<PathBuf>,
+    /// Modification time of the file in seconds since the unix epoch.
+    #[serde(default)]
+    pub modified: Option<u64>,
+    /// Displayed width and height, read from the file header when the reference was scanned.
+    #[serde(default)]
+    pub dimensions: Option<(u32, u32)>,
+    /// Identity of the file content, computed in the background after scanning.
+    /// For sequences this is the hash of the first frame.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<ContentHash>,
+}
+impl Reference {
+    /// Reads the reference at `path` if it is a file in one of `formats`.
+    pub fn read(path: PathBuf, formats: &BTreeSet<RefFormat>) -> Option<Reference> {
+        // format and dimensions are read from the same bytes, so the file is only opened once
+        let header = archive::read_header(&path, decode::HEADER_LEN).ok()?;
+        let format = RefFormat::from_header(&header)?;
+        if !formats.contains(&format) {
+            return None;
+        }
+        let modified = archive::container_metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|modified| modified.as_secs());
+        Some(Reference {
+            dimensions: decode::dimensions_from(Cursor::new(header)),
+            path,
+            format: Some(format),
+            sequence: Vec::new(),
+            modified,
+            hash: None,
+        })
+    }
+    pub fn is_sequence(&self) -> bool {
+        !self.sequence.is_empty()
+    }
 }
 impl PartialEq for Reference {
     fn eq(&self, other: &Self) -> bool {
         self.path.eq(&other.path)
     }
 }
-fn recursive_get_imgs(
-    source_path: &Path,
-    follow_root_links: bool,
-) -> impl Iterator<Item = Reference> {
-    walkdir::WalkDir::new(source_path)
-        .follow_root_links(follow_root_links)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .map(|e| e.into_path())
-        .filter(|p| p.is_file())
-        .filter(|p| {
-            p.extension()
-                .map(|ext| ext.to_ascii_lowercase())
-                .map(|ext| {
-                    ext == OsStr::new("jpg")
-                        || ext == OsStr::new("png")
-                        || ext == OsStr::new("webp")
-                })
-                .unwrap_or_default()
-        })
-        .map(move |image_path| Reference { path: image_path })
+impl Hash for Reference {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.path.hash(state);
+    }
+}
+/// `path` moved from below `from` to below `to`.
+/// Returns `None` if `path` is not below `from`.
+pub fn rebase(path: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+    let relative = path.strip_prefix(from).ok()?;
+    if relative.as_os_str().is_empty() {
+        return Some(to.to_path_buf());
+    }
+    Some(to.join(relative))
+}
+
+/// Result of checking a relocated source folder against the index of its old location.
+#[derive(Debug, Clone, Default)]
+pub struct RelocationReport {
+    /// New path of the source folder.
+    pub source: PathBuf,
+    /// Number of indexed files that were checked.
+    pub checked: usize,
+    /// Files that do not exist at the new location.
+    pub missing: Vec<PathBuf>,
+    /// Files whose size differs from the one at the old location.
+    pub changed: Vec<PathBuf>,
+}
+impl RelocationReport {
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Minimum number of consecutively numbered images that form a sequence.
+const MIN_SEQUENCE_LEN: usize = 3;
+
+/// Groups images in the same folder which only differ in a consecutive number
+/// into a single reference. All other references are returned unchanged.
+fn group_sequences(references: impl Iterator<Item = Reference>) -> Vec<Reference> {
+    let mut result = Vec::new();
+    // key is everything of the path except the number, ordered so grouping is the same every run
+    let mut candidates: BTreeMap<(PathBuf, String, String), Vec<(u64, Reference)>> =
+        BTreeMap::new();
+    for reference in references {
+        match sequence_key(&reference.path) {
+            Some((key, number)) => candidates.entry(key).or_default().push((number, reference)),
+            None => result.push(reference),
+        }
+    }
+    for mut group in candidates.into_values() {
+        group.sort_by_key(|(number, _)| *number);
+        let mut run: Vec<(u64, Reference)> = Vec::new();
+        for (number, reference) in group {
+            if run
+                .last()
+                .is_some_and(|(last, _)| last.checked_add(1) != Some(number))
+            {
+                push_run(&mut result, std::mem::take(&mut run));
+            }
+            run.push((number, reference));
+        }
+        push_run(&mut result, run);
+    }
+    result
+}
+fn push_run(result: &mut Vec<Reference>, run: Vec<(u64, Reference)>) {
+    if run.len() < MIN_SEQUENCE_LEN {
+        result.extend(run.into_iter().map(|(_, reference)| reference));
+        return;
+    }
+    let sequence = run
+        .iter()
+        .map(|(_, reference)| reference.path.clone())
+        .collect();
+    let (_, first) = run.into_iter().next().expect("run is not empty");
+    result.push(Reference { sequence, ..first });
+}
+/// Splits the file name of `path` at its last number.
+/// Returns the parts around the number as key together with the number.
+fn sequence_key(path: &Path) -> Option<((PathBuf, String, String), u64)> {
+    let name = path.file_name()?.to_str()?;
+    let stem_len = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .map_or(name.len(), str::len);
+    let stem = &name[..stem_len];
+    let end = stem.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = stem[..end]
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |i| i + 1);
+    let number = stem[start..end].parse().ok()?;
+    let key = (
+        path.parent()?.to_path_buf(),
+        name[..start].to_string(),
+        name[end..].to_ascii_lowercase(),
+    );
+    Some((key, number))
+}
+
+/// Image formats a reference can have.
+///
+/// The format is detected from the magic bytes at the start of a file,
+/// so misnamed files or files without extension are still picked up.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize, serde::Serialize,
+)]
+pub enum RefFormat {
+    Jpeg,
+    Png,
+    Webp,
+    Gif,
+    Bmp,
+    Tiff,
+}
+impl RefFormat {
+    pub const ALL: [RefFormat; 6] = [
+        RefFormat::Jpeg,
+        RefFormat::Png,
+        RefFormat::Webp,
+        RefFormat::Gif,
+        RefFormat::Bmp,
+        RefFormat::Tiff,
+    ];
+    /// Formats accepted by newly added sources.
+    pub fn default_formats() -> BTreeSet<RefFormat> {
+        Self::ALL.into_iter().collect()
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            RefFormat::Jpeg => "jpeg",
+            RefFormat::Png => "png",
+            RefFormat::Webp => "webp",
+            RefFormat::Gif => "gif",
+            RefFormat::Bmp => "bmp",
+            RefFormat::Tiff => "tiff",
+        }
+    }
+    pub fn image_format(self) -> ImageFormat {
+        match self {
+            RefFormat::Jpeg => ImageFormat::Jpeg,
+            RefFormat::Png => ImageFormat::Png,
+            RefFormat::Webp => ImageFormat::WebP,
+            RefFormat::Gif => ImageFormat::Gif,
+            RefFormat::Bmp => ImageFormat::Bmp,
+            RefFormat::Tiff => ImageFormat::Tiff,
+        }
+    }
+    /// Detects the format of the file at `path` by reading its first bytes.
+    /// Returns `None` if the file can not be read or is not a supported image.
+    pub fn sniff(path: &Path) -> Option<RefFormat> {
+        let header = archive::read_header(path, 32).ok()?;
+        Self::from_header(&header)
+    }
+    pub fn from_header(header: &[u8]) -> Option<RefFormat> {
+        // `image::guess_format` only checks for `RIFF`, which also matches wav and avi files
+        if header.starts_with(b"RIFF") {
+            return (header.get(8..12) == Some(b"WEBP")).then_some(RefFormat::Webp);
+        }
+        match image::guess_format(header).ok()? {
+            ImageFormat::Jpeg => Some(RefFormat::Jpeg),
+            ImageFormat::Png => Some(RefFormat::Png),
+            ImageFormat::Gif => Some(RefFormat::Gif),
+            ImageFormat::Bmp => Some(RefFormat::Bmp),
+            ImageFormat::Tiff => Some(RefFormat::Tiff),
+            _ => None,
+        }
+    }
+}
+/// How explicit the content of a reference is, ordered from least to most explicit.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+pub enum ContentRating {
+    #[default]
+    General,
+    ArtisticNudity,
+    Explicit,
+}
+impl ContentRating {
+    pub const ALL: [ContentRating; 3] = [
+        ContentRating::General,
+        ContentRating::ArtisticNudity,
+        ContentRating::Explicit,
+    ];
+    /// Name used in queries.
+    pub fn name(self) -> &'static str {
+        match self {
+            ContentRating::General => "general",
+            ContentRating::ArtisticNudity => "artistic",
+            ContentRating::Explicit => "explicit",
+        }
+    }
 }
-#[derive(Eq, Hash, Debug, Clone, serde::Deserialize, serde::Serialize)]
+
+#[derive(Eq, Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(from = "StoredSourceFolder")]
 pub struct SourceFolder {
     pub path: PathBuf,
-    pub is_sfw: bool,
+    /// Rating of all references in the folder which do not override it.
+    pub rating: ContentRating,
+    /// Formats that are picked up when scanning the folder.
+    pub formats: BTreeSet<RefFormat>,
+    /// Whether numbered images like `pose_001.jpg … pose_040.jpg` are grouped into one reference.
+    pub detect_sequences: bool,
+    pub scan_options: ScanOptions,
+    /// Files found by the last scan, stored in [`index::INDEX_FILENAME`].
+    #[serde(skip)]
+    pub index: SourceIndex,
+    /// The folder could not be scanned, e.g. because its drive is not mounted.
+    /// It keeps the references of its last scan, which are not sampled until it is available again.
+    #[serde(skip)]
+    pub offline: bool,
     #[serde(skip)]
     pub children: Vec<Reference>,
 }
+/// [`SourceFolder`] as it is read from disk.
+/// Stores written before content ratings existed only contain an `is_sfw` flag.
+#[derive(serde::Deserialize)]
+#[serde(rename = "SourceFolder")]
+struct StoredSourceFolder {
+    path: PathBuf,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    rating: Option<ContentRating>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    is_sfw: Option<bool>,
+    #[serde(
+        default = "RefFormat::default_formats",
+        deserialize_with = "deserialize_formats"
+    )]
+    formats: BTreeSet<RefFormat>,
+    #[serde(default)]
+    detect_sequences: bool,
+    #[serde(default)]
+    scan_options: ScanOptions,
+}
+/// Reads a present field as `Some`, as the stored fields are written without the `Some(…)` wrapper.
+fn deserialize_some<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+/// Reads the stored formats without the ones that are not supported anymore.
+/// Avif could be selected before, although the `image` crate is built without an avif decoder.
+fn deserialize_formats<'de, D>(deserializer: D) -> Result<BTreeSet<RefFormat>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    enum StoredFormat {
+        Jpeg,
+        Png,
+        Webp,
+        Gif,
+        Bmp,
+        Tiff,
+        #[serde(other)]
+        Unsupported,
+    }
+    let formats: Vec<StoredFormat> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(formats
+        .into_iter()
+        .filter_map(|format| match format {
+            StoredFormat::Jpeg => Some(RefFormat::Jpeg),
+            StoredFormat::Png => Some(RefFormat::Png),
+            StoredFormat::Webp => Some(RefFormat::Webp),
+            StoredFormat::Gif => Some(RefFormat::Gif),
+            StoredFormat::Bmp => Some(RefFormat::Bmp),
+            StoredFormat::Tiff => Some(RefFormat::Tiff),
+            StoredFormat::Unsupported => None,
+        })
+        .collect())
+}
+impl From<StoredSourceFolder> for SourceFolder {
+    fn from(stored: StoredSourceFolder) -> Self {
+        // folders that were not sfw may contain anything
+        let legacy_rating = stored.is_sfw.map(|is_sfw| {
+            if is_sfw {
+                ContentRating::General
+            } else {
+                ContentRating::Explicit
+            }
+        });
+        SourceFolder {
+            path: stored.path,
+            rating: stored.rating.or(legacy_rating).unwrap_or_default(),
+            formats: stored.formats,
+            detect_sequences: stored.detect_sequences,
+            scan_options: stored.scan_options,
+            index: SourceIndex::default(),
+            offline: false,
+            children: Vec::new(),
+        }
+    }
+}
+impl SourceFolder {
+    /// Replaces the index with the result of a scan and updates the references from it.
+    /// Hashes of unchanged files are kept, and hashed files that vanished are remembered.
+    pub fn set_index(&mut self, mut index: SourceIndex) {
+        index.merge_hashes(&self.index);
+        index.inherit_vanished(&self.index);
+        self.index = index;
+        self.offline = false;
+        self.update_children();
+    }
+    /// Updates the references from the index, e.g. after sequence detection was toggled.
+    pub fn update_children(&mut self) {
+        let children = self.index.references();
+        self.children = if self.detect_sequences {
+            group_sequences(children)
+        } else {
+            children.collect()
+        };
+    }
+    /// Marks the files and folders at `paths` which lie in this source folder as changed,
+    /// so the next scan reads them again. Returns whether any of the paths lies in this folder.
+    pub fn invalidate_paths(&mut self, paths: &BTreeSet<PathBuf>) -> bool {
+        let changed: Vec<&Path> = paths
+            .iter()
+            .filter(|path| path.starts_with(&self.path))
+            .map(PathBuf::as_path)
+            .collect();
+        if changed.is_empty() {
+            return false;
+        }
+        self.index.invalidate(changed);
+        true
+    }
+    /// Number of references per detected format.
+    pub fn format_counts(&self) -> BTreeMap<RefFormat, usize> {
+        let mut counts = BTreeMap::new();
+        for format in self.children.iter().filter_map(|child| child.format) {
+            *counts.entry(format).or_default() += 1;
+        }
+        counts
+    }
+}
 impl PartialEq for SourceFolder {
     fn eq(&self, other: &Self) -> bool {
         self.path.eq(&other.path)
     }
 }
+impl Hash for SourceFolder {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.path.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// File names of every grouped reference, the frames for sequences.
+    fn grouped(names: &[&str]) -> Vec<Vec<String>> {
+        let references = names.iter().map(|name| Reference {
+            path: Path::new("/refs").join(name),
+            ..Reference::default()
+        });
+        group_sequences(references)
+            .into_iter()
+            .map(|reference| {
+                let frames = if reference.is_sequence() {
+                    reference.sequence
+                } else {
+                    vec![reference.path]
+                };
+                frames
+                    .iter()
+                    .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sequences_need_min_sequence_len_images() {
+        assert_eq!(
+            grouped(&["pose_1.jpg", "pose_2.jpg"]),
+            [["pose_1.jpg"], ["pose_2.jpg"]]
+        );
+        assert_eq!(
+            grouped(&["pose_1.jpg", "pose_2.jpg", "pose_3.jpg"]),
+            [["pose_1.jpg", "pose_2.jpg", "pose_3.jpg"]]
+        );
+    }
+
+    #[test]
+    fn sequences_are_ordered_by_number() {
+        assert_eq!(
+            grouped(&["pose_010.jpg", "pose_009.jpg", "pose_011.jpg"]),
+            [["pose_009.jpg", "pose_010.jpg", "pose_011.jpg"]]
+        );
+        assert_eq!(
+            grouped(&["pose_10.jpg", "pose_9.jpg", "pose_11.jpg"]),
+            [["pose_9.jpg", "pose_10.jpg", "pose_11.jpg"]]
+        );
+    }
+
+    #[test]
+    fn gaps_split_sequences() {
+        assert_eq!(
+            grouped(&["a1.png", "a2.png", "a3.png", "a5.png", "a6.png"]),
+            [
+                vec!["a1.png", "a2.png", "a3.png"],
+                vec!["a5.png"],
+                vec!["a6.png"]
+            ]
+        );
+        assert_eq!(
+            grouped(&["a1.png", "a2.png", "a3.png", "a5.png", "a6.png", "a7.png"]),
+            [
+                ["a1.png", "a2.png", "a3.png"],
+                ["a5.png", "a6.png", "a7.png"]
+            ]
+        );
+    }
+
+    #[test]
+    fn largest_numbers_do_not_overflow() {
+        assert_eq!(
+            grouped(&[
+                "f18446744073709551613.png",
+                "f18446744073709551614.png",
+                "f18446744073709551615.png",
+            ]),
+            [[
+                "f18446744073709551613.png",
+                "f18446744073709551614.png",
+                "f18446744073709551615.png"
+            ]]
+        );
+        // too large for a number, so it is not part of any sequence
+        assert_eq!(
+            grouped(&["f18446744073709551616.png"]),
+            [["f18446744073709551616.png"]]
+        );
+    }
+
+    #[test]
+    fn grouping_does_not_depend_on_scan_order() {
+        let names = [
+            "b_2.jpg",
+            "a_1.jpg",
+            "cover.jpg",
+            "b_1.jpg",
+            "a_3.jpg",
+            "b_3.jpg",
+            "a_2.jpg",
+        ];
+        let mut reversed = names;
+        reversed.reverse();
+        let expected = [
+            vec!["cover.jpg"],
+            vec!["a_1.jpg", "a_2.jpg", "a_3.jpg"],
+            vec!["b_1.jpg", "b_2.jpg", "b_3.jpg"],
+        ];
+        assert_eq!(grouped(&names), expected);
+        assert_eq!(grouped(&reversed), expected);
+    }
+
+    #[test]
+    fn is_sfw_of_old_stores_becomes_a_rating() {
+        let stored = r#"(
+    source_folders: [
+        (
+            path: "/refs/safe",
+            is_sfw: true,
+        ),
+        (
+            path: "/refs/unsafe",
+            is_sfw: false,
+        ),
+    ],
+)"#;
+        let ref_store: RefStore = ron::from_str(stored).expect("old store is readable");
+        let ratings: Vec<ContentRating> = ref_store
+            .source_folders
+            .iter()
+            .map(|source| source.rating)
+            .collect();
+        assert_eq!(ratings, [ContentRating::General, ContentRating::Explicit]);
+    }
+}
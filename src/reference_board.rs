@@ -1,4 +1,4 @@
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
 use cosmic::{
     iced::{
@@ -13,7 +13,10 @@ use cosmic::{
     },
 };
 
-use crate::app::{self, Message};
+use crate::{
+    animation::{self, LoadedReference, Playback, PlaybackTarget},
+    app::{self, Message},
+};
 
 pub struct ReferenceBoard {
     pub panes: pane_grid::State<ReferenceNode>,
@@ -25,9 +28,67 @@ pub struct ReferenceNode {
     pub id: usize,
     pub is_pinned: bool,
     pub path: PathBuf,
+    #[serde(skip)]
+    pub playback: Playback,
 }
 
 impl ReferenceBoard {
+    /// A board with one pane per path, laid out in a balanced grid.
+    /// Returns `None` if `paths` is empty.
+    pub fn from_paths(paths: &[PathBuf]) -> Option<ReferenceBoard> {
+        if paths.is_empty() {
+            return None;
+        }
+        let nodes: Vec<ReferenceNode> = paths
+            .iter()
+            .enumerate()
+            .map(|(id, path)| ReferenceNode {
+                id,
+                is_pinned: false,
+                path: path.clone(),
+                playback: Playback::default(),
+            })
+            .collect();
+        Some(ReferenceBoard {
+            panes: pane_grid::State::with_configuration(layout(nodes, Axis::Vertical)),
+            focus: None,
+            panes_created: paths.len(),
+        })
+    }
+    /// Adds a pane for every path not on the board yet by splitting the focused pane,
+    /// or the last created one, with alternating axes. Existing panes keep their sizes.
+    pub fn append(&mut self, paths: &[PathBuf]) {
+        let mut target = self.focus.or_else(|| {
+            self.panes
+                .iter()
+                .max_by_key(|(_, node)| node.id)
+                .map(|(pane, _)| *pane)
+        });
+        let mut axis = Axis::Vertical;
+        for path in paths {
+            if self.panes.iter().any(|(_, node)| &node.path == path) {
+                continue;
+            }
+            let Some(pane) = target else {
+                break;
+            };
+            let node = ReferenceNode {
+                id: self.panes_created,
+                is_pinned: false,
+                path: path.clone(),
+                playback: Playback::default(),
+            };
+            let Some((new_pane, _)) = self.panes.split(axis, pane, node) else {
+                break;
+            };
+            self.panes_created += 1;
+            target = Some(new_pane);
+            axis = match axis {
+                Axis::Vertical => Axis::Horizontal,
+                Axis::Horizontal => Axis::Vertical,
+            };
+        }
+    }
     // pub fn try_load() -> Option<ReferenceBoard> {
     //     io::try_load(REF_BOARD_FILENAME)
     // }
@@ -41,6 +102,7 @@ impl Default for ReferenceBoard {
             path: "/home/emi/refs/dancing_blonde/DSC_6801.jpg".into(),
             id: 0,
             is_pinned: false,
+            playback: Playback::default(),
         });
         ReferenceBoard {
             panes: pane_state,
@@ -49,6 +111,24 @@ impl Default for ReferenceBoard {
         }
     }
 }
+/// Splits `nodes` in half along `axis`, alternating the axis for every level.
+fn layout(mut nodes: Vec<ReferenceNode>, axis: Axis) -> pane_grid::Configuration<ReferenceNode> {
+    if nodes.len() == 1 {
+        return pane_grid::Configuration::Pane(nodes.remove(0));
+    }
+    let b = nodes.split_off(nodes.len() / 2);
+    let ratio = nodes.len() as f32 / (nodes.len() + b.len()) as f32;
+    let next_axis = match axis {
+        Axis::Vertical => Axis::Horizontal,
+        Axis::Horizontal => Axis::Vertical,
+    };
+    pane_grid::Configuration::Split {
+        axis,
+        ratio,
+        a: Box::new(layout(nodes, next_axis)),
+        b: Box::new(layout(b, next_axis)),
+    }
+}
 pub fn keypress(key_press: Key) -> Option<Message> {
     match key_press.as_ref() {
         keyboard::Key::Character("v") => Some(Message::SplitFocused(Axis::Vertical)),
@@ -68,8 +148,25 @@ pub fn keypress(key_press: Key) -> Option<Message> {
         _ => None,
     }
 }
-pub fn view_content<'a>(image_path: &Path) -> cosmic::Element<'a, app::Message> {
-    let content = widget::image(image_path);
+pub fn view_content<'a>(
+    pane: pane_grid::Pane,
+    node: &ReferenceNode,
+    loaded: Option<&LoadedReference>,
+) -> cosmic::Element<'a, app::Message> {
+    let Some(loaded) = loaded else {
+        // not decoded yet, let iced load the file on its own in the meantime
+        return cosmic::widget::container(scrollable(widget::image(node.path.as_path())))
+            .center_y(Fill)
+            .padding(5)
+            .into();
+    };
+    let content = cosmic::widget::column()
+        .push(animation::view(loaded, &node.playback))
+        .push_maybe(
+            loaded
+                .is_animated()
+                .then(|| animation::controls(loaded, &node.playback, PlaybackTarget::Pane(pane))),
+        );
 
     cosmic::widget::container(scrollable(content))
         .center_y(Fill)
@@ -0,0 +1,207 @@
+//! Scanning and hashing of source folders in the background, so large libraries do not freeze
+//! the window.
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use cosmic::iced::{futures::Stream, task};
+use futures_util::SinkExt;
+
+use crate::{
+    app::Message,
+    content_hash::HashedFile,
+    index::{self, SourceIndex},
+    reference::{RefFormat, SourceFolder},
+};
+
+/// Progress is reported at most this often, so scanning does not redraw the window constantly.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+pub enum ScanUpdate {
+    Progress {
+        /// Number of references found so far.
+        files: usize,
+        /// Directory that is scanned right now.
+        directory: PathBuf,
+    },
+    /// The source folder at `path` was scanned completely.
+    Finished { path: PathBuf, index: SourceIndex },
+    /// The source folder at `path` is not available, see [`SourceIndex::is_available`].
+    Offline { path: PathBuf },
+}
+
+/// Content hashes are sent at most this often, as the index is saved with every batch.
+const HASH_BATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub enum HashUpdate {
+    /// Content hashes of some of the files, see [`crate::content_hash`].
+    Hashed(Vec<HashedFile>),
+    /// All files were hashed.
+    Finished,
+}
+
+/// Source folders waiting to be scanned and the scan that is running.
+#[derive(Debug, Default)]
+pub struct ScanQueue {
+    pending: VecDeque<PathBuf>,
+    pub running: Option<RunningScan>,
+}
+impl ScanQueue {
+    /// Adds `paths` to the queue. A running scan of one of them is cancelled, so it starts over
+    /// with the current settings of the source.
+    pub fn push(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        for path in paths {
+            if self
+                .running
+                .as_ref()
+                .is_some_and(|scan| scan.source == path)
+            {
+                self.cancel_running();
+            }
+            if !self.pending.contains(&path) {
+                self.pending.push_back(path);
+            }
+        }
+    }
+    /// Whether the source folder at `path` is scanned or waits to be scanned.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.running
+            .as_ref()
+            .is_some_and(|scan| scan.source == path)
+            || self.pending.iter().any(|pending| pending == path)
+    }
+    /// Ends the running scan of `path`.
+    /// Returns `false` if `path` is not scanned anymore, e.g. because the scan was cancelled.
+    pub fn finish(&mut self, path: &Path) -> bool {
+        if !self
+            .running
+            .as_ref()
+            .is_some_and(|scan| scan.source == path)
+        {
+            return false;
+        }
+        self.running = None;
+        true
+    }
+    pub fn pop(&mut self) -> Option<PathBuf> {
+        self.pending.pop_front()
+    }
+    /// Number of source folders waiting for the running scan.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+    fn cancel_running(&mut self) {
+        if let Some(scan) = self.running.take() {
+            scan.handle.abort();
+        }
+    }
+    /// Cancels the running scan and all pending ones.
+    /// The source folders keep the references of their last complete scan.
+    pub fn cancel_all(&mut self) {
+        self.pending.clear();
+        self.cancel_running();
+    }
+}
+
+#[derive(Debug)]
+pub struct RunningScan {
+    /// Path of the source folder being scanned.
+    pub source: PathBuf,
+    pub files: usize,
+    pub directory: Option<PathBuf>,
+    handle: task::Handle,
+}
+impl RunningScan {
+    pub fn new(source: PathBuf, handle: task::Handle) -> RunningScan {
+        RunningScan {
+            source,
+            files: 0,
+            directory: None,
+            handle,
+        }
+    }
+}
+
+/// Scans `source` on a blocking thread and streams its progress.
+///
+/// Dropping the stream, e.g. by aborting its task, stops the scan at the next directory.
+pub fn scan(source: &SourceFolder) -> impl Stream<Item = Message> {
+    let path = source.path.clone();
+    let formats: BTreeSet<RefFormat> = source.formats.clone();
+    let options = source.scan_options.clone();
+    let previous = source.index.clone();
+    cosmic::iced::stream::channel(16, move |mut output| async move {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        tokio::task::spawn_blocking(move || {
+            if !previous.is_available(&path) {
+                let _ = sender.send(ScanUpdate::Offline { path });
+                return;
+            }
+            let mut last_report = Instant::now();
+            let mut report = |directory: &Path, files| {
+                if last_report.elapsed() < PROGRESS_INTERVAL {
+                    return ControlFlow::Continue(());
+                }
+                last_report = Instant::now();
+                let progress = ScanUpdate::Progress {
+                    files,
+                    directory: directory.to_path_buf(),
+                };
+                // the receiver is gone once the scan was cancelled
+                match sender.send(progress) {
+                    Ok(()) => ControlFlow::Continue(()),
+                    Err(_) => ControlFlow::Break(()),
+                }
+            };
+            let index = previous.rescan(&path, &formats, &options, &mut report);
+            if let Some(index) = index {
+                let _ = sender.send(ScanUpdate::Finished { path, index });
+            }
+        });
+        while let Some(update) = receiver.recv().await {
+            if output.send(Message::Scan(update)).await.is_err() {
+                break;
+            }
+        }
+    })
+}
+
+/// Hashes the content of `files`, given with their indexed size and modification time,
+/// on a blocking thread and streams the hashes in batches.
+///
+/// Dropping the stream, e.g. by aborting its task, stops hashing after the current file.
+pub fn hash(files: Vec<(PathBuf, u64, u64)>) -> impl Stream<Item = Message> {
+    cosmic::iced::stream::channel(16, move |mut output| async move {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        tokio::task::spawn_blocking(move || {
+            let mut batch = Vec::new();
+            let mut last_batch = Instant::now();
+            for (path, size, modified) in files {
+                // the receiver is gone once hashing was cancelled
+                if sender.is_closed() {
+                    return;
+                }
+                batch.extend(index::hash_unchanged(&path, size, modified));
+                if !batch.is_empty() && last_batch.elapsed() >= HASH_BATCH_INTERVAL {
+                    last_batch = Instant::now();
+                    let _ = sender.send(HashUpdate::Hashed(std::mem::take(&mut batch)));
+                }
+            }
+            if !batch.is_empty() {
+                let _ = sender.send(HashUpdate::Hashed(batch));
+            }
+            let _ = sender.send(HashUpdate::Finished);
+        });
+        while let Some(update) = receiver.recv().await {
+            if output.send(Message::Hashing(update)).await.is_err() {
+                break;
+            }
+        }
+    })
+}
@@ -0,0 +1,228 @@
+//! Per-source settings which files and directories are scanned.
+//!
+//! Patterns follow gitignore: a pattern without a slash matches a file or directory name at any
+//! depth, a pattern with a slash is matched against the path relative to the source folder, and a
+//! trailing slash only matches directories. Files in a skipped directory are never scanned,
+//! so an include pattern can not bring them back.
+
+use std::path::{Component, Path};
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Which files and directories of a source folder are scanned.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    /// Patterns of files and directories that are skipped, like `_unused/` or `.thumbnails/`.
+    pub exclude: Vec<String>,
+    /// Patterns of files and directories that are scanned even if they match [`Self::exclude`].
+    pub include: Vec<String>,
+    /// Directories nested deeper below the source folder are skipped.
+    /// With 0 only the files directly in the source folder are scanned.
+    pub max_depth: Option<usize>,
+    /// Smaller files, like previews other apps place next to the images, are skipped.
+    pub min_file_size: u64,
+    /// Whether links to files and directories inside the source folder are followed.
+    /// A linked source folder itself is always scanned, as it was chosen explicitly.
+    pub follow_links: bool,
+}
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            exclude: Vec::new(),
+            include: Vec::new(),
+            max_depth: None,
+            min_file_size: 0,
+            follow_links: true,
+        }
+    }
+}
+impl ScanOptions {
+    pub fn filter(&self) -> Result<ScanFilter, globset::Error> {
+        Ok(ScanFilter {
+            exclude: Patterns::new(&self.exclude)?,
+            include: Patterns::new(&self.include)?,
+        })
+    }
+    /// Whether the directory `depth` levels below the source folder is scanned.
+    pub fn scans_depth(&self, depth: usize) -> bool {
+        self.max_depth.is_none_or(|max_depth| depth <= max_depth)
+    }
+    /// Whether a file of `size` bytes is large enough to be scanned.
+    pub fn scans_size(&self, size: u64) -> bool {
+        size >= self.min_file_size
+    }
+    pub fn apply(&mut self, edit: ScanOptionsEdit) {
+        match edit {
+            ScanOptionsEdit::Exclude(patterns) => self.exclude = split_patterns(&patterns),
+            ScanOptionsEdit::Include(patterns) => self.include = split_patterns(&patterns),
+            ScanOptionsEdit::MaxDepth(max_depth) => self.max_depth = max_depth,
+            ScanOptionsEdit::MinFileSize(size) => self.min_file_size = size,
+            ScanOptionsEdit::FollowLinks(follow_links) => self.follow_links = follow_links,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ScanOptionsEdit {
+    /// Comma separated patterns.
+    Exclude(String),
+    /// Comma separated patterns.
+    Include(String),
+    MaxDepth(Option<usize>),
+    /// In bytes.
+    MinFileSize(u64),
+    FollowLinks(bool),
+}
+
+/// Patterns as they are edited, separated by commas.
+pub fn join_patterns(patterns: &[String]) -> String {
+    patterns.join(",")
+}
+/// Splits edited patterns without trimming them, so joining them again gives back the same text.
+/// Whitespace around the patterns is ignored when they are matched.
+fn split_patterns(patterns: &str) -> Vec<String> {
+    if patterns.is_empty() {
+        return Vec::new();
+    }
+    patterns.split(',').map(str::to_string).collect()
+}
+
+/// Compiled patterns of [`ScanOptions`].
+#[derive(Debug, Clone, Default)]
+pub struct ScanFilter {
+    exclude: Patterns,
+    include: Patterns,
+}
+impl ScanFilter {
+    /// Whether the file or directory at `relative`, relative to the source folder, is skipped.
+    /// Its parent directories are not checked.
+    pub fn skips(&self, relative: &Path, is_dir: bool) -> bool {
+        self.exclude.matches(relative, is_dir) && !self.include.matches(relative, is_dir)
+    }
+    /// Like [`Self::skips`], but also checks the directories between the source folder and `relative`.
+    pub fn skips_path(&self, relative: &Path, is_dir: bool) -> bool {
+        let mut ancestors = relative.ancestors().skip(1);
+        self.skips(relative, is_dir)
+            || ancestors.any(|dir| !dir.as_os_str().is_empty() && self.skips(dir, true))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Patterns {
+    /// Patterns which match files and directories.
+    any: GlobSet,
+    /// Patterns with a trailing slash.
+    directories: GlobSet,
+}
+impl Patterns {
+    fn new(patterns: &[String]) -> Result<Patterns, globset::Error> {
+        let mut any = GlobSetBuilder::new();
+        let mut directories = GlobSetBuilder::new();
+        for pattern in patterns.iter().map(|pattern| pattern.trim()) {
+            if pattern.is_empty() {
+                continue;
+            }
+            match pattern.strip_suffix('/') {
+                Some(pattern) => directories.add(glob(pattern)?),
+                None => any.add(glob(pattern)?),
+            };
+        }
+        Ok(Patterns {
+            any: any.build()?,
+            directories: directories.build()?,
+        })
+    }
+    fn matches(&self, relative: &Path, is_dir: bool) -> bool {
+        self.any.is_match(relative) || (is_dir && self.directories.is_match(relative))
+    }
+}
+
+fn glob(pattern: &str) -> Result<Glob, globset::Error> {
+    let anchored = match pattern.strip_prefix('/') {
+        Some(pattern) => pattern.to_string(),
+        None if pattern.contains('/') => pattern.to_string(),
+        None => format!("**/{pattern}"),
+    };
+    GlobBuilder::new(&anchored).literal_separator(true).build()
+}
+
+/// Number of directories between the source folder and the directory at `relative`,
+/// which is 0 for the source folder itself.
+pub fn depth_of_dir(relative: &Path) -> usize {
+    relative
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(exclude: &[&str], include: &[&str]) -> ScanFilter {
+        ScanOptions {
+            exclude: exclude.iter().map(|pattern| pattern.to_string()).collect(),
+            include: include.iter().map(|pattern| pattern.to_string()).collect(),
+            ..ScanOptions::default()
+        }
+        .filter()
+        .expect("patterns are valid")
+    }
+
+    #[test]
+    fn patterns_without_slash_match_at_any_depth() {
+        let filter = filter(&["cover.jpg"], &[]);
+        assert!(filter.skips(Path::new("cover.jpg"), false));
+        assert!(filter.skips(Path::new("a/b/cover.jpg"), false));
+        assert!(!filter.skips(Path::new("a/cover.jpg.bak"), false));
+    }
+
+    #[test]
+    fn patterns_with_slash_are_anchored() {
+        let filter = filter(&["/cover.jpg", "a/b.jpg"], &[]);
+        assert!(filter.skips(Path::new("cover.jpg"), false));
+        assert!(!filter.skips(Path::new("a/cover.jpg"), false));
+        assert!(filter.skips(Path::new("a/b.jpg"), false));
+        assert!(!filter.skips(Path::new("x/a/b.jpg"), false));
+    }
+
+    #[test]
+    fn trailing_slash_only_matches_directories() {
+        let filter = filter(&["raw/"], &[]);
+        assert!(filter.skips(Path::new("raw"), true));
+        assert!(filter.skips(Path::new("a/raw"), true));
+        assert!(!filter.skips(Path::new("raw"), false));
+        assert!(filter.skips_path(Path::new("a/raw/pose.jpg"), false));
+        assert!(!filter.skips_path(Path::new("a/pose.jpg"), false));
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_directories() {
+        let any_depth = filter(&["photos/**/*.psd"], &[]);
+        assert!(any_depth.skips(Path::new("photos/a.psd"), false));
+        assert!(any_depth.skips(Path::new("photos/a/b/c.psd"), false));
+        assert!(!any_depth.skips(Path::new("other/photos/a.psd"), false));
+        // a single star does not cross directories
+        let one_level = filter(&["photos/*.psd"], &[]);
+        assert!(!one_level.skips(Path::new("photos/a/b.psd"), false));
+    }
+
+    #[test]
+    fn include_overrides_exclude_but_not_skipped_directories() {
+        let filter = filter(&["*.png", "_unused/"], &["keep.png", "_unused/keep.jpg"]);
+        assert!(filter.skips(Path::new("a.png"), false));
+        assert!(!filter.skips(Path::new("a/keep.png"), false));
+        assert!(filter.skips_path(Path::new("_unused/keep.jpg"), false));
+    }
+
+    #[test]
+    fn largest_min_file_size_skips_everything_smaller() {
+        let mut options = ScanOptions::default();
+        assert!(options.scans_size(0));
+        options.apply(ScanOptionsEdit::MinFileSize(u64::MAX));
+        assert!(!options.scans_size(0));
+        assert!(!options.scans_size(u64::MAX - 1));
+        assert!(options.scans_size(u64::MAX));
+    }
+}
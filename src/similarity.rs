@@ -0,0 +1,112 @@
+//! "More like this": ranking the references of the library by how similar they look to one of them.
+//!
+//! Everything is computed locally from the cached thumbnails. A reference is described by a
+//! coarse color histogram, its perceptual hash and its aspect ratio, which are cached with the
+//! thumbnails, so only new or changed files are decoded.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use image::RgbaImage;
+
+use crate::{
+    decode::DecodeLimits,
+    duplicates::{self, PerceptualHash},
+    thumbnail,
+};
+
+const FEATURES_FILENAME: &str = "similarity_features.ron";
+/// Number of levels each color channel is quantized to for the histogram.
+const LEVELS: usize = 4;
+/// The histogram is normalized so its bins sum up to this.
+const HISTOGRAM_TOTAL: u32 = 4096;
+/// Number of results of a search.
+pub const MAX_RESULTS: usize = 60;
+/// Only this many results are placed on the reference board, so the panes stay usable.
+pub const MAX_BOARD_RESULTS: usize = 6;
+
+const COLOR_WEIGHT: f32 = 0.5;
+const SHAPE_WEIGHT: f32 = 0.35;
+const ASPECT_WEIGHT: f32 = 0.15;
+
+/// Visual features of a reference.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Features {
+    /// Share of the pixels in each bin of colors, quantized to [`LEVELS`] per channel.
+    histogram: Vec<u16>,
+    hash: PerceptualHash,
+    /// Width divided by height.
+    aspect_ratio: f32,
+}
+impl Features {
+    pub fn compute(img: &RgbaImage) -> Features {
+        let bin = |channel: u8| channel as usize * LEVELS / 256;
+        let mut counts = vec![0u32; LEVELS.pow(3)];
+        for pixel in img.pixels() {
+            let [r, g, b, _] = pixel.0;
+            counts[(bin(r) * LEVELS + bin(g)) * LEVELS + bin(b)] += 1;
+        }
+        let pixels = (img.width() * img.height()).max(1) as u64;
+        let histogram = counts
+            .into_iter()
+            .map(|count| (count as u64 * HISTOGRAM_TOTAL as u64 / pixels) as u16)
+            .collect();
+        Features {
+            histogram,
+            hash: duplicates::difference_hash(img),
+            aspect_ratio: img.width() as f32 / img.height().max(1) as f32,
+        }
+    }
+    /// Distance between 0 for identical and 1 for completely different looking references.
+    pub fn distance(&self, other: &Features) -> f32 {
+        let color_difference: u32 = self
+            .histogram
+            .iter()
+            .zip(&other.histogram)
+            .map(|(a, b)| a.abs_diff(*b) as u32)
+            .sum();
+        let colors = (color_difference as f32 / (2 * HISTOGRAM_TOTAL) as f32).min(1.);
+        let shape = (self.hash ^ other.hash).count_ones() as f32 / PerceptualHash::BITS as f32;
+        // a reference twice as wide as the other one counts as completely different
+        let aspect = ((self.aspect_ratio / other.aspect_ratio).ln().abs() / 2f32.ln()).min(1.);
+        COLOR_WEIGHT * colors + SHAPE_WEIGHT * shape + ASPECT_WEIGHT * aspect
+    }
+}
+
+/// Features of `paths`. Paths that can not be decoded are left out.
+///
+/// Only files without cached features are read, through their thumbnail.
+pub fn features_all(paths: &[PathBuf], limits: &DecodeLimits) -> HashMap<PathBuf, Features> {
+    thumbnail::derive_cached(paths, FEATURES_FILENAME, limits, Features::compute)
+}
+
+/// Up to [`MAX_RESULTS`] paths of `features` that look most like `target`, most similar first.
+/// `target` itself is not part of the result.
+pub fn rank(target: &Path, features: &HashMap<PathBuf, Features>) -> Vec<PathBuf> {
+    let Some(target_features) = features.get(target) else {
+        return Vec::new();
+    };
+    let mut ranked: Vec<(f32, &PathBuf)> = features
+        .iter()
+        .filter(|(path, _)| path.as_path() != target)
+        .map(|(path, features)| (target_features.distance(features), path))
+        .collect();
+    ranked.sort_unstable_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(b.1)));
+    ranked
+        .into_iter()
+        .take(MAX_RESULTS)
+        .map(|(_, path)| path.clone())
+        .collect()
+}
+
+/// The last "more like this" search.
+#[derive(Debug, Default)]
+pub struct SimilarSearch {
+    /// The reference similar ones are searched for.
+    pub target: Option<PathBuf>,
+    /// Most similar first.
+    pub results: Vec<PathBuf>,
+    pub running: bool,
+}
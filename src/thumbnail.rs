@@ -0,0 +1,240 @@
+//! Persistent cache of small previews of references.
+//!
+//! Thumbnails are stored in the XDG cache directory. Their file name is derived from the
+//! path, size, modification time and length of the source file, so a changed file
+//! simply produces a new key and stale thumbnails are removed by [`prune`].
+//! Values derived from thumbnails, like perceptual hashes, are cached with the same keys.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::UNIX_EPOCH,
+};
+
+use image::{codecs::jpeg::JpegEncoder, RgbaImage};
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::warn;
+
+use crate::{
+    archive,
+    decode::{self, DecodeLimits},
+    reference::RefFormat,
+};
+
+/// Edge length of the square thumbnails are fitted into.
+pub const THUMBNAIL_SIZE: u32 = 256;
+const THUMBNAIL_QUALITY: u8 = 85;
+/// Upper bound in bytes a decoder may allocate for generating a thumbnail.
+/// Only jpegs are decoded downsampled, so larger images of other formats get no thumbnail
+/// instead of decoding hundreds of megabytes for a preview, on every thread generating one.
+const THUMBNAIL_MAX_ALLOC: u64 = 256 * 1024 * 1024;
+
+fn cache_dir() -> Option<PathBuf> {
+    let project_dirs = directories_next::ProjectDirs::from("", "", "refline")?;
+    Some(project_dirs.cache_dir().join("thumbnails"))
+}
+
+/// Key of the thumbnail of `path` with the given `size`.
+/// Returns `None` if the file can not be accessed.
+/// Entries of archives use the size and modification time of their archive.
+pub fn cache_key(path: &Path, size: u32) -> Option<String> {
+    let metadata = archive::container_metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(key_of(path, size, modified.as_nanos(), metadata.len()))
+}
+
+/// Key of the thumbnail of `path` from its indexed size and modification time in nanoseconds,
+/// which is the one [`cache_key`] returns as long as the file did not change since.
+pub fn indexed_key(path: &Path, size: u32, len: u64, modified: u64) -> String {
+    key_of(path, size, u128::from(modified), len)
+}
+
+fn key_of(path: &Path, size: u32, modified: u128, len: u64) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(path.as_os_str().as_encoded_bytes());
+    hasher.update(&size.to_le_bytes());
+    hasher.update(&modified.to_le_bytes());
+    hasher.update(&len.to_le_bytes());
+    let mut key = hasher.finalize().to_hex().to_string();
+    key.truncate(32);
+    key
+}
+
+fn thumbnail_path(cache_dir: &Path, key: &str, size: u32) -> PathBuf {
+    // sharded by the first byte, so no directory has to hold the whole library
+    cache_dir
+        .join(size.to_string())
+        .join(&key[..2])
+        .join(format!("{key}.jpg"))
+}
+
+/// Returns the thumbnail of `path`, generating and caching it if necessary.
+///
+/// Reads the cached jpeg, or decodes the source image if there is none yet.
+pub fn load_or_generate(path: &Path, size: u32, limits: &DecodeLimits) -> Option<RgbaImage> {
+    let key = cache_key(path, size)?;
+    let cache_dir = cache_dir()?;
+    let thumbnail_path = thumbnail_path(&cache_dir, &key, size);
+    if let Ok(thumbnail) = image::open(&thumbnail_path) {
+        return Some(thumbnail.to_rgba8());
+    }
+    let thumbnail = generate(path, size, limits)?;
+    if let Err(e) = save(&thumbnail, &thumbnail_path) {
+        warn!("Could not save thumbnail of {path:?} at {thumbnail_path:?} with error {e:?}");
+    }
+    Some(thumbnail)
+}
+
+fn generate(path: &Path, size: u32, limits: &DecodeLimits) -> Option<RgbaImage> {
+    // asking for twice the size lets jpegs be decoded at a fraction of their resolution
+    let limits = DecodeLimits {
+        max_pixels: (size as u64 * 2).pow(2),
+        max_alloc: limits.max_alloc.min(THUMBNAIL_MAX_ALLOC),
+    };
+    match decode::decode_still(path, RefFormat::sniff(path), &limits) {
+        Ok((img, _)) => Some(img.thumbnail(size, size).to_rgba8()),
+        Err(e) => {
+            warn!("Could not generate thumbnail of {path:?} with error {e:?}");
+            None
+        }
+    }
+}
+
+/// Writes to a temporary file next to `thumbnail_path` and renames it,
+/// so other threads loading the thumbnail never see a partly written jpeg.
+fn save(thumbnail: &RgbaImage, thumbnail_path: &Path) -> image::ImageResult<()> {
+    if let Some(parent) = thumbnail_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_path = temp_path(thumbnail_path);
+    let result = fs::File::create(&temp_path)
+        .map_err(image::ImageError::from)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            let rgb = image::DynamicImage::ImageRgba8(thumbnail.clone()).to_rgb8();
+            JpegEncoder::new_with_quality(&mut writer, THUMBNAIL_QUALITY).encode_image(&rgb)?;
+            Ok(writer.flush()?)
+        })
+        .and_then(|()| Ok(fs::rename(&temp_path, thumbnail_path)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+/// Unique path of a temporary file next to `path`, which is renamed to `path` once written.
+fn temp_path(path: &Path) -> PathBuf {
+    static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    PathBuf::from(temp_path)
+}
+
+/// Values derived from the thumbnails of `paths`, which are left out if they have no thumbnail.
+///
+/// The values are cached in `file_name` in the cache directory, so they are only derived again
+/// for changed files. Only the values of `paths` are kept, so values of removed files are dropped,
+/// unless one of `paths` can not be read right now.
+/// Reads the whole cache file and the thumbnail of every path whose value is not cached,
+/// which may mean decoding its source image.
+pub fn derive_cached<T: Clone + Serialize + DeserializeOwned>(
+    paths: &[PathBuf],
+    file_name: &str,
+    limits: &DecodeLimits,
+    derive: impl Fn(&RgbaImage) -> T,
+) -> HashMap<PathBuf, T> {
+    let cache_path = cache_dir().map(|cache_dir| cache_dir.join(file_name));
+    let cache: HashMap<String, T> = cache_path.as_deref().map(load_derived).unwrap_or_default();
+    let mut updated_cache = HashMap::with_capacity(paths.len());
+    let mut values = HashMap::with_capacity(paths.len());
+    let mut unchecked = false;
+    for path in paths {
+        let Some(key) = cache_key(path, THUMBNAIL_SIZE) else {
+            unchecked = true;
+            continue;
+        };
+        let value = match cache.get(&key) {
+            Some(value) => value.clone(),
+            None => match load_or_generate(path, THUMBNAIL_SIZE, limits) {
+                Some(thumbnail) => derive(&thumbnail),
+                None => continue,
+            },
+        };
+        updated_cache.insert(key, value.clone());
+        values.insert(path.clone(), value);
+    }
+    // the key of a file that can not be read right now, e.g. on an unmounted drive, is unknown,
+    // so all values are kept until it is available again
+    if unchecked {
+        for (key, value) in cache {
+            updated_cache.entry(key).or_insert(value);
+        }
+    }
+    if let Some(cache_path) = cache_path {
+        save_derived(&cache_path, &updated_cache);
+    }
+    values
+}
+
+fn load_derived<T: DeserializeOwned>(cache_path: &Path) -> HashMap<String, T> {
+    let Ok(file) = fs::File::open(cache_path) else {
+        return HashMap::new();
+    };
+    ron::de::from_reader(file).unwrap_or_else(|e| {
+        warn!("Could not read cache at {cache_path:?} with error {e:?}");
+        HashMap::new()
+    })
+}
+
+/// Writes `cache` to a file of its own first and renames it over `cache_path`, so searches
+/// running at the same time never read a partly written cache.
+fn save_derived<T: Serialize>(cache_path: &Path, cache: &HashMap<String, T>) {
+    let temp_path = temp_path(cache_path);
+    let result = cache_path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::File::create(&temp_path))
+        .map_err(ron::Error::from)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            ron::ser::to_writer(&mut writer, cache)?;
+            writer
+                .into_inner()
+                .map_err(|e| e.into_error())?
+                .sync_all()?;
+            Ok(())
+        })
+        .and_then(|()| fs::rename(&temp_path, cache_path).map_err(ron::Error::from));
+    if let Err(e) = result {
+        warn!("Could not save cache at {cache_path:?} with error {e:?}");
+        let _ = fs::remove_file(&temp_path);
+    }
+}
+
+/// Removes all cached thumbnails of `size` whose key is not one of `valid`.
+/// Returns the number of removed thumbnails.
+///
+/// Lists every cached thumbnail of `size`, without reading them.
+pub fn prune(valid: &HashSet<String>, size: u32) -> usize {
+    let Some(cache_dir) = cache_dir() else {
+        return 0;
+    };
+    walkdir::WalkDir::new(cache_dir.join(size.to_string()))
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| {
+            !e.path()
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|key| valid.contains(key))
+        })
+        .filter(|e| fs::remove_file(e.path()).is_ok())
+        .count()
+}
@@ -1,12 +1,26 @@
+use crate::album::AlbumEdit;
+use crate::animation::{self, PlaybackTarget};
 use crate::app::Message;
-use crate::app::{self, AppModel};
+use crate::app::{self, AppModel, Thumbnail};
+use crate::browser;
+use crate::collection::{CollectionEdit, SortOrder};
+use crate::duplicates::SearchState;
+use crate::export::{ExportMode, ExportNames, ExportOptions, ExportSource};
+use crate::figure_drawing::SessionSource;
 use crate::fl;
+use crate::metadata::{MetadataEdit, ReferenceMeta};
+use crate::query::{QueryInput, QueryTarget};
+use crate::reference::{ContentRating, RefFormat, SourceFolder};
 use crate::reference_board::{view_content, view_controls};
+use crate::scan_options::{self, ScanOptionsEdit};
 use cosmic::iced::alignment::{Horizontal, Vertical};
+use cosmic::iced::widget::scrollable::{Direction, Scrollbar};
+use cosmic::iced::Alignment;
 use cosmic::iced::Length::{self, Fill};
 use cosmic::iced_widget::{button, row};
 use cosmic::widget::{self, text, PaneGrid};
 use cosmic::{Apply, Element};
+use std::path::Path;
 
 pub fn figure_drawing(app: &AppModel) -> Element<app::Message> {
     let ref_store = &app.ref_store;
@@ -19,16 +33,50 @@ pub fn figure_drawing(app: &AppModel) -> Element<app::Message> {
         tracing::error!("index points to invalid history point");
         return center_text(fl!("add_refs"));
     };
-    let Some(img) = ref_store.ref_data.get(&reference.path).cloned() else {
+    let Some(loaded) = ref_store.ref_data.get(&reference.path) else {
         tracing::warn!("image not loaded yet");
         return center_text(fl!("loading"));
     };
-    let handle =
-        cosmic::widget::image::Handle::from_rgba(img.width(), img.height(), img.into_vec());
-    let toggler = widget::toggler(figure_drawing_state.sfw_only).on_toggle(Message::SetSfwFilter);
-    let image = widget::Image::new(handle);
+    let mut max_rating = widget::row()
+        .push(text(fl!("max_content")))
+        .spacing(8)
+        .align_y(Alignment::Center);
+    for rating in ContentRating::ALL {
+        max_rating = max_rating.push(rating_button(
+            content_rating_name(rating),
+            figure_drawing_state.max_rating == rating,
+            Message::SetMaxRating(rating),
+        ));
+    }
+    let image = animation::view(loaded, &figure_drawing_state.playback);
     widget::column()
-        .push(row![widget::text("sfw_filter_active"), toggler])
+        .push_maybe(warning_banner(app))
+        .push(max_rating)
+        .push(session_source(app))
+        .push(query_input(
+            &figure_drawing_state.query,
+            QueryTarget::FigureDrawing,
+        ))
+        .push(metadata_editor(app, &reference.path))
+        .push(
+            widget::row()
+                .push(album_picker(app, &reference.path))
+                .push(
+                    button(text(fl!("more_like_this")).size(14))
+                        .padding(3)
+                        .on_press(Message::FindSimilarToCurrent),
+                )
+                .spacing(12)
+                .align_y(Alignment::Center),
+        )
+        .push_maybe(animation::warning(loaded))
+        .push_maybe(loaded.is_animated().then(|| {
+            animation::controls(
+                loaded,
+                &figure_drawing_state.playback,
+                PlaybackTarget::FigureDrawing,
+            )
+        }))
         .push(image)
         .into()
 }
@@ -68,7 +116,11 @@ pub fn reference_board(app: &AppModel) -> Element<app::Message> {
             .controls(title_bar_controls)
             .padding(10);
 
-        widget::pane_grid::Content::new(view_content(&pane.path)).title_bar(title_bar)
+        let loaded = app.ref_store.ref_data.get(&pane.path);
+        let content = widget::column()
+            .push_maybe(loaded.and_then(animation::warning))
+            .push(view_content(id, pane, loaded));
+        widget::pane_grid::Content::new(content).title_bar(title_bar)
     })
     .width(Fill)
     .height(Fill)
@@ -82,27 +134,883 @@ pub fn reference_board(app: &AppModel) -> Element<app::Message> {
         .height(Fill)
         .padding(10);
 
-    cont.into()
+    widget::column()
+        .push_maybe(warning_banner(app))
+        .push(cont)
+        .into()
 }
 pub fn reference_store(app: &AppModel) -> Element<app::Message> {
+    widget::column()
+        .push_maybe(warning_banner(app))
+        .push_maybe(scan_progress(app))
+        .push(source_folders(app))
+        .push_maybe(relocation_report(app))
+        .push(duplicates(app))
+        .push(browser::view(app))
+        .spacing(12)
+        .padding(10)
+        .into()
+}
+/// Settings of the smart collection with `id` above the browser showing its references.
+pub fn collection(app: &AppModel, id: u32) -> Element<app::Message> {
+    let Some(collection) = app.ref_store.collection(id) else {
+        return center_text(fl!("no_references"));
+    };
+    let error = collection.query().err();
+    let mut sort = widget::row()
+        .push(text(fl!("sort")))
+        .spacing(8)
+        .align_y(Alignment::Center);
+    for order in SortOrder::ALL {
+        let label = sort_order_name(order);
+        let label = if collection.sort == order {
+            format!("[{label}]")
+        } else {
+            label
+        };
+        sort = sort.push(
+            button(text(label).size(14))
+                .padding(3)
+                .on_press(Message::EditCollection(id, CollectionEdit::SetSort(order))),
+        );
+    }
+    let actions = widget::row()
+        .push(
+            button(text(fl!("use_for_session")).size(14))
+                .padding(3)
+                .on_press(Message::SetSessionSource(SessionSource::Collection(id))),
+        )
+        .push(
+            button(text(fl!("remove_collection")).size(14))
+                .padding(3)
+                .on_press(Message::RemoveCollection(id)),
+        )
+        .spacing(8);
+    widget::column()
+        .push_maybe(warning_banner(app))
+        .push(
+            widget::text_input(fl!("collection_name"), &collection.name)
+                .on_input(move |name| Message::EditCollection(id, CollectionEdit::Rename(name))),
+        )
+        .push(
+            widget::text_input(fl!("query_placeholder"), &collection.query).on_input(
+                move |query| Message::EditCollection(id, CollectionEdit::SetQuery(query)),
+            ),
+        )
+        .push_maybe(error.map(|error| widget::text::caption(error.to_string())))
+        .push(sort)
+        .push(actions)
+        .push(browser::view(app))
+        .spacing(12)
+        .padding(10)
+        .into()
+}
+fn sort_order_name(order: SortOrder) -> String {
+    match order {
+        SortOrder::Path => fl!("sort_path"),
+        SortOrder::Name => fl!("sort_name"),
+        SortOrder::NewestFirst => fl!("sort_newest"),
+        SortOrder::OldestFirst => fl!("sort_oldest"),
+        SortOrder::LargestFirst => fl!("sort_largest"),
+    }
+}
+/// Buttons to choose whether the figure drawing session samples from all references or a collection.
+fn session_source(app: &AppModel) -> Element<app::Message> {
+    let current = app.figure_drawing_state.source;
+    let source_button = |label: String, source: SessionSource| {
+        let label = if current == source {
+            format!("[{label}]")
+        } else {
+            label
+        };
+        button(text(label).size(14))
+            .padding(3)
+            .on_press(Message::SetSessionSource(source))
+    };
+    let mut row = widget::row()
+        .push(source_button(fl!("all_references"), SessionSource::All))
+        .spacing(8);
+    for collection in &app.ref_store.collections {
+        row = row.push(source_button(
+            collection.name.clone(),
+            SessionSource::Collection(collection.id),
+        ));
+    }
+    for album in &app.albums.albums {
+        row = row.push(source_button(
+            album.name.clone(),
+            SessionSource::Album(album.id),
+        ));
+    }
+    if !app.similar.results.is_empty() {
+        row = row.push(source_button(fl!("similar"), SessionSource::Similar));
+    }
+    row.into()
+}
+/// Buttons to choose the active album and to add the reference at `path` to it.
+pub fn album_picker<'a>(app: &'a AppModel, path: &Path) -> Element<'a, app::Message> {
+    let active = app.albums.active_album();
+    let mut row = widget::row()
+        .push(text(fl!("album")))
+        .spacing(8)
+        .align_y(Alignment::Center);
+    for album in &app.albums.albums {
+        let label = if active.is_some_and(|active| active.id == album.id) {
+            format!("[{}]", album.name)
+        } else {
+            album.name.clone()
+        };
+        row = row.push(
+            button(text(label).size(14))
+                .padding(3)
+                .on_press(Message::SetActiveAlbum(album.id)),
+        );
+    }
+    row = row.push(
+        button(text(fl!("new_album_button")).size(14))
+            .padding(3)
+            .on_press(Message::AddAlbum),
+    );
+    if let Some(active) = active {
+        let label = if active.contains(path) {
+            fl!("remove_from_album")
+        } else {
+            fl!("add_to_album")
+        };
+        row = row.push(
+            button(text(label).size(14))
+                .padding(3)
+                .on_press(Message::ToggleInActiveAlbum),
+        );
+    }
+    row.into()
+}
+/// The references of the album with `id` in their order, with controls to rearrange them.
+pub fn album(app: &AppModel, id: u32) -> Element<app::Message> {
+    let Some(album) = app.albums.get(id) else {
+        return center_text(fl!("no_references"));
+    };
+    let actions = widget::row()
+        .push(
+            button(text(fl!("use_for_session")).size(14))
+                .padding(3)
+                .on_press(Message::SetSessionSource(SessionSource::Album(id))),
+        )
+        .push(
+            button(text(fl!("album_to_board")).size(14))
+                .padding(3)
+                .on_press(Message::AlbumToBoard(id)),
+        )
+        .push(
+            button(text(fl!("export")).size(14))
+                .padding(3)
+                .on_press(Message::OpenExport(ExportSource::Album(id))),
+        )
+        .push(
+            button(text(fl!("remove_album")).size(14))
+                .padding(3)
+                .on_press(Message::RemoveAlbum(id)),
+        )
+        .spacing(8);
+    let mut references = widget::column().spacing(4);
+    if album.references.is_empty() {
+        references = references.push(text(fl!("empty_album")));
+    }
+    let last = album.references.len().saturating_sub(1);
+    for (index, path) in album.references.iter().enumerate() {
+        let thumbnail: Element<app::Message> = match app.thumbnails.get(path) {
+            Some(Thumbnail::Loaded(handle)) => widget::image(handle.clone())
+                .width(Length::Fixed(64.))
+                .height(Length::Fixed(64.))
+                .into(),
+            _ => widget::Space::new(Length::Fixed(64.), Length::Fixed(64.)).into(),
+        };
+        let move_to = |to: usize| Message::EditAlbum(id, AlbumEdit::Move { from: index, to });
+        references = references.push(
+            widget::row()
+                .push(thumbnail)
+                .push(
+                    button(text("↑").size(14))
+                        .padding(3)
+                        .on_press_maybe((index > 0).then(|| move_to(index - 1))),
+                )
+                .push(
+                    button(text("↓").size(14))
+                        .padding(3)
+                        .on_press_maybe((index < last).then(|| move_to(index + 1))),
+                )
+                .push(
+                    button(text("x").size(14))
+                        .padding(3)
+                        .on_press(Message::EditAlbum(id, AlbumEdit::Remove(path.clone()))),
+                )
+                .push(text(path.display().to_string()))
+                .spacing(8)
+                .align_y(Alignment::Center),
+        );
+    }
+    widget::column()
+        .push_maybe(warning_banner(app))
+        .push(
+            widget::text_input(fl!("album_name"), &album.name)
+                .on_input(move |name| Message::EditAlbum(id, AlbumEdit::Rename(name))),
+        )
+        .push(actions)
+        .push(widget::scrollable(references).height(Fill))
+        .spacing(12)
+        .padding(10)
+        .into()
+}
+/// Files of the last relocated source folder that were not found at the new location.
+fn relocation_report(app: &AppModel) -> Option<Element<app::Message>> {
+    /// Only the first mismatches are listed, the rest is counted.
+    const LISTED: usize = 10;
+    let report = app.relocation.as_ref()?;
+    let found = report.checked - report.missing.len() - report.changed.len();
+    let summary = if report.is_complete() {
+        fl!("relocation_complete", files = report.checked)
+    } else {
+        fl!(
+            "relocation_mismatches",
+            found = found,
+            files = report.checked,
+            missing = report.missing.len(),
+            changed = report.changed.len()
+        )
+    };
+    let header = widget::row()
+        .push(text(summary))
+        .push(button(widget::text("x")).on_press(Message::DismissRelocation))
+        .spacing(8)
+        .align_y(Alignment::Center);
+    let mut list = widget::column().push(header).spacing(2);
+    let mismatches = report
+        .missing
+        .iter()
+        .map(|path| (path, fl!("missing")))
+        .chain(report.changed.iter().map(|path| (path, fl!("changed"))));
+    for (path, kind) in mismatches.clone().take(LISTED) {
+        let relative = path.strip_prefix(&report.source).unwrap_or(path);
+        list = list.push(widget::text::caption(format!(
+            "{kind}: {}",
+            relative.display()
+        )));
+    }
+    let unlisted = mismatches.count().saturating_sub(LISTED);
+    if unlisted > 0 {
+        list = list.push(widget::text::caption(fl!(
+            "more_mismatches",
+            count = unlisted
+        )));
+    }
+    Some(list.into())
+}
+/// Source folder that is scanned right now, with a button to stop scanning.
+fn scan_progress(app: &AppModel) -> Option<Element<app::Message>> {
+    let scan = app.scan.running.as_ref()?;
+    let name = scan
+        .source
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| scan.source.to_string_lossy().to_string());
+    let mut status = fl!("scanning_source", source = name, files = scan.files);
+    let queued = app.scan.pending_count();
+    if queued > 0 {
+        status = format!("{status} {}", fl!("scans_queued", count = queued));
+    }
+    let directory = scan
+        .directory
+        .as_ref()
+        .and_then(|directory| directory.strip_prefix(&scan.source).ok())
+        .map(|directory| widget::text::caption(directory.to_string_lossy().to_string()));
+    let header = widget::row()
+        .push(text(status))
+        .push(
+            button(text(fl!("cancel_scan")).size(14))
+                .padding(3)
+                .on_press(Message::CancelScan),
+        )
+        .spacing(8)
+        .align_y(Alignment::Center);
+    Some(
+        widget::column()
+            .push(header)
+            .push_maybe(directory)
+            .spacing(4)
+            .into(),
+    )
+}
+/// Groups of near-duplicate references, one of which can be compared side by side.
+fn duplicates(app: &AppModel) -> Element<app::Message> {
+    let groups = &app.ref_store.duplicates;
+    let status = match app.duplicate_search {
+        SearchState::Idle => fl!("duplicates", count = groups.groups.len()),
+        SearchState::Running | SearchState::Outdated => fl!("searching_duplicates"),
+    };
+    let skipped = groups.skipped_count();
+    let header = widget::row()
+        .push(text(status))
+        .push_maybe(
+            (app.duplicate_search == SearchState::Idle && skipped > 0)
+                .then(|| widget::text::caption(fl!("duplicates_skipped", count = skipped))),
+        )
+        .push(
+            button(text(fl!("find_duplicates")).size(14))
+                .padding(3)
+                .on_press_maybe(
+                    (app.duplicate_search == SearchState::Idle).then_some(Message::FindDuplicates),
+                ),
+        )
+        .spacing(8)
+        .align_y(Alignment::Center);
+    let Some((index, group)) = app
+        .compared_duplicates
+        .and_then(|index| groups.get(index).map(|group| (index, group)))
+    else {
+        let mut list = widget::row().spacing(4);
+        for (index, group) in groups.groups.iter().enumerate() {
+            let name = group[0]
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            list = list.push(
+                button(text(format!("{name} ({})", group.len())).size(14))
+                    .padding(3)
+                    .on_press(Message::CompareDuplicates(Some(index))),
+            );
+        }
+        return widget::column()
+            .push(header)
+            .push(horizontal_scrollable(list))
+            .spacing(4)
+            .into();
+    };
+    let mut comparison = widget::row().spacing(12);
+    for path in group {
+        let thumbnail: Element<app::Message> = match app.thumbnails.get(path) {
+            Some(Thumbnail::Loaded(handle)) => widget::image(handle.clone())
+                .width(Length::Fixed(256.))
+                .height(Length::Fixed(256.))
+                .into(),
+            _ => widget::Space::new(Length::Fixed(256.), Length::Fixed(256.)).into(),
+        };
+        let dimensions = app
+            .ref_store
+            .find(path)
+            .and_then(|(_, reference)| reference.dimensions)
+            .map(|(width, height)| widget::text::caption(format!("{width} x {height}")));
+        let hidden = app.ref_store.is_hidden(path);
+        let hide_label = if hidden {
+            fl!("restore_duplicate")
+        } else {
+            fl!("hide_duplicate")
+        };
+        comparison =
+            comparison.push(
+                widget::column()
+                    .push(thumbnail)
+                    .push(widget::text::caption(path.display().to_string()))
+                    .push_maybe(dimensions)
+                    .push(button(text(hide_label).size(14)).padding(3).on_press(
+                        Message::EditMetadata(path.clone(), MetadataEdit::SetHidden(!hidden)),
+                    ))
+                    .width(Length::Fixed(256.))
+                    .spacing(4),
+            );
+    }
+    let navigation = widget::row()
+        .push(
+            button(text("←").size(14)).padding(3).on_press_maybe(
+                index
+                    .checked_sub(1)
+                    .map(|previous| Message::CompareDuplicates(Some(previous))),
+            ),
+        )
+        .push(text(format!("{} / {}", index + 1, groups.groups.len())))
+        .push(
+            button(text("→").size(14)).padding(3).on_press_maybe(
+                (index + 1 < groups.groups.len())
+                    .then_some(Message::CompareDuplicates(Some(index + 1))),
+            ),
+        )
+        .push(
+            button(text(fl!("close_comparison")).size(14))
+                .padding(3)
+                .on_press(Message::CompareDuplicates(None)),
+        )
+        .spacing(8)
+        .align_y(Alignment::Center);
+    widget::column()
+        .push(header)
+        .push(navigation)
+        .push(horizontal_scrollable(comparison))
+        .spacing(4)
+        .into()
+}
+fn horizontal_scrollable<'a>(
+    content: impl Into<Element<'a, app::Message>>,
+) -> Element<'a, app::Message> {
+    widget::scrollable(content)
+        .direction(Direction::Horizontal(Scrollbar::new()))
+        .into()
+}
+/// Results of the last "more like this" search, which search further when clicked.
+pub fn similar(app: &AppModel) -> Element<app::Message> {
+    const COLUMNS: usize = 6;
+    let search = &app.similar;
+    let Some(target) = &search.target else {
+        return center_text(fl!("no_similar_search"));
+    };
+    let status = if search.running {
+        fl!("searching_similar")
+    } else {
+        fl!("similar_results", count = search.results.len())
+    };
+    let has_results = !search.results.is_empty();
+    let header =
+        widget::row()
+            .push(similar_thumbnail(app, target))
+            .push(
+                widget::column()
+                    .push(text(target.display().to_string()))
+                    .push(text(status))
+                    .push(
+                        widget::row()
+                            .push(
+                                button(text(fl!("use_for_session")).size(14))
+                                    .padding(3)
+                                    .on_press_maybe(has_results.then_some(
+                                        Message::SetSessionSource(SessionSource::Similar),
+                                    )),
+                            )
+                            .push(
+                                button(text(fl!("similar_to_board")).size(14))
+                                    .padding(3)
+                                    .on_press_maybe(has_results.then_some(Message::SimilarToBoard)),
+                            )
+                            .spacing(8),
+                    )
+                    .spacing(8),
+            )
+            .spacing(12)
+            .align_y(Alignment::Center);
+    let mut results = widget::column().spacing(8);
+    for chunk in search.results.chunks(COLUMNS) {
+        let mut cells = widget::row().spacing(8);
+        for path in chunk {
+            cells = cells.push(
+                widget::button::custom(similar_thumbnail(app, path))
+                    .on_press(Message::FindSimilar(path.clone())),
+            );
+        }
+        results = results.push(cells);
+    }
+    widget::column()
+        .push_maybe(warning_banner(app))
+        .push(header)
+        .push(widget::scrollable(results).height(Fill))
+        .spacing(12)
+        .padding(10)
+        .into()
+}
+fn similar_thumbnail<'a>(app: &'a AppModel, path: &Path) -> Element<'a, app::Message> {
+    match app.thumbnails.get(path) {
+        Some(Thumbnail::Loaded(handle)) => widget::image(handle.clone())
+            .width(Length::Fixed(128.))
+            .height(Length::Fixed(128.))
+            .into(),
+        _ => widget::Space::new(Length::Fixed(128.), Length::Fixed(128.)).into(),
+    }
+}
+fn source_folders(app: &AppModel) -> Element<app::Message> {
     let mut grid = widget::Grid::new();
     grid = grid.push(button(widget::text(fl!("add_source"))).on_press(Message::AddFilesToRefStore));
+    grid = grid
+        .push(button(widget::text(fl!("add_archive"))).on_press(Message::AddArchivesToRefStore));
     grid = grid.insert_row();
     grid = grid.push(widget::text("path"));
-    grid = grid.push(widget::text("is_sfw"));
+    grid = grid.push(widget::text(fl!("content")));
+    grid = grid.push(widget::text(fl!("sequences")));
+    grid = grid.push(widget::text(fl!("formats")));
+    grid = grid.push(widget::text(fl!("scan_options")));
+    grid = grid.push(widget::text(fl!("relocate_source")));
     grid = grid.push(widget::text(fl!("remove_source")));
     grid = grid.insert_row();
     for source in &app.ref_store.source_folders {
-        grid = grid.push(widget::text(format!("{:?}", &source.path)));
+        let offline = source
+            .offline
+            .then(|| widget::text::caption(fl!("source_offline")));
+        grid = grid.push(
+            widget::column()
+                .push(widget::text(format!("{:?}", &source.path)))
+                .push_maybe(offline),
+        );
+        let mut rating = widget::row().spacing(4);
+        for content in ContentRating::ALL {
+            rating = rating.push(rating_button(
+                content_rating_name(content),
+                source.rating == content,
+                Message::SetSourceRating(content, source.path.clone()),
+            ));
+        }
+        grid = grid.push(rating);
+        grid = grid.push(
+            widget::toggler(source.detect_sequences)
+                .on_toggle(|detect| Message::SetSourceSequences(detect, source.path.clone())),
+        );
+        let counts = source.format_counts();
+        let mut formats = widget::row().spacing(8);
+        for format in RefFormat::ALL {
+            let count = counts.get(&format).copied().unwrap_or_default();
+            let path = source.path.clone();
+            formats = formats.push(
+                widget::checkbox(
+                    format!("{} ({count})", format.name()),
+                    source.formats.contains(&format),
+                )
+                .on_toggle(move |enabled| Message::SetSourceFormat {
+                    path: path.clone(),
+                    format,
+                    enabled,
+                }),
+            );
+        }
+        grid = grid.push(formats);
+        grid = grid.push(scan_options_editor(source));
         grid = grid.push(
-            widget::toggler(source.is_sfw)
-                .on_toggle(|is_sfw| Message::SetSfwSource(is_sfw, source.path.clone())),
+            button(widget::text(fl!("relocate")))
+                .on_press(Message::RelocateSource(source.path.clone())),
         );
         grid = grid.push(button(widget::text("x")).on_press(Message::RemoveSource(source.clone())));
         grid = grid.insert_row();
     }
     grid.into()
 }
+/// Patterns, depth, size and link settings of `source`, which are applied by scanning it again.
+fn scan_options_editor(source: &SourceFolder) -> Element<app::Message> {
+    let options = &source.scan_options;
+    let path = source.path.clone();
+    let edit = move |edit: ScanOptionsEdit| Message::EditScanOptions(path.clone(), edit);
+    let apply = Message::ApplyScanOptions(source.path.clone());
+    let error = options.filter().err();
+    let max_depth = options.max_depth;
+    let min_kib = options.min_file_size / 1024;
+    let patterns = widget::row()
+        .push(
+            widget::text_input(
+                fl!("exclude_patterns"),
+                scan_options::join_patterns(&options.exclude),
+            )
+            .on_input({
+                let edit = edit.clone();
+                move |patterns| edit(ScanOptionsEdit::Exclude(patterns))
+            })
+            .on_submit(apply.clone())
+            .width(Length::Fixed(200.)),
+        )
+        .push(
+            widget::text_input(
+                fl!("include_patterns"),
+                scan_options::join_patterns(&options.include),
+            )
+            .on_input({
+                let edit = edit.clone();
+                move |patterns| edit(ScanOptionsEdit::Include(patterns))
+            })
+            .on_submit(apply.clone())
+            .width(Length::Fixed(200.)),
+        )
+        .spacing(4);
+    // input that is not a number keeps the previous value
+    let limits = widget::row()
+        .push(text(fl!("max_depth")).size(14))
+        .push(
+            widget::text_input(
+                fl!("unlimited"),
+                max_depth.map(|depth| depth.to_string()).unwrap_or_default(),
+            )
+            .on_input({
+                let edit = edit.clone();
+                move |depth| {
+                    let depth = if depth.is_empty() {
+                        None
+                    } else {
+                        depth.parse().ok().or(max_depth)
+                    };
+                    edit(ScanOptionsEdit::MaxDepth(depth))
+                }
+            })
+            .on_submit(apply.clone())
+            .width(Length::Fixed(80.)),
+        )
+        .push(text(fl!("min_file_size")).size(14))
+        .push(
+            widget::text_input("0", min_kib.to_string())
+                .on_input({
+                    let edit = edit.clone();
+                    move |kib| {
+                        let kib = if kib.is_empty() {
+                            0
+                        } else {
+                            kib.parse().unwrap_or(min_kib)
+                        };
+                        edit(ScanOptionsEdit::MinFileSize(kib.saturating_mul(1024)))
+                    }
+                })
+                .on_submit(apply.clone())
+                .width(Length::Fixed(80.)),
+        )
+        .push(
+            widget::checkbox(fl!("follow_links"), options.follow_links)
+                .on_toggle(move |follow| edit(ScanOptionsEdit::FollowLinks(follow))),
+        )
+        .push(
+            button(text(fl!("apply_scan_options")).size(14))
+                .padding(3)
+                .on_press_maybe(
+                    (error.is_none() && options != source.index.options()).then_some(apply),
+                ),
+        )
+        .spacing(8)
+        .align_y(Alignment::Center);
+    widget::column()
+        .push(patterns)
+        .push(limits)
+        .push_maybe(error.map(|error| widget::text::caption(error.to_string())))
+        .spacing(4)
+        .into()
+}
+/// Text input for a query with the parse error shown below it.
+pub fn query_input<'a>(input: &'a QueryInput, target: QueryTarget) -> Element<'a, app::Message> {
+    widget::column()
+        .push(
+            widget::text_input(fl!("query_placeholder"), &input.text)
+                .on_input(move |text| Message::SetQuery(target, text)),
+        )
+        .push_maybe(
+            input
+                .error
+                .as_ref()
+                .map(|error| widget::text::caption(error.to_string())),
+        )
+        .spacing(2)
+        .into()
+}
+/// Favorite flag, rating, tags and notes of the reference at `path`.
+pub fn metadata_editor<'a>(app: &'a AppModel, path: &Path) -> Element<'a, app::Message> {
+    let meta = app.ref_store.metadata(path).cloned().unwrap_or_default();
+    let owned_path = path.to_path_buf();
+    let edit = move |edit: MetadataEdit| Message::EditMetadata(owned_path.clone(), edit);
+
+    let mut rating = widget::row().spacing(2);
+    for stars in 1..=ReferenceMeta::MAX_RATING {
+        let label = if meta.rating.is_some_and(|rating| rating >= stars) {
+            "★"
+        } else {
+            "☆"
+        };
+        rating = rating.push(
+            button(text(label).size(16))
+                .padding(2)
+                .on_press(edit(MetadataEdit::SetRating(stars))),
+        );
+    }
+    let mut tags = widget::row().spacing(4).align_y(Alignment::Center);
+    for tag in &meta.tags {
+        tags = tags.push(
+            button(text(format!("{tag} ×")).size(14))
+                .padding(3)
+                .on_press(edit(MetadataEdit::RemoveTag(tag.clone()))),
+        );
+    }
+    tags = tags
+        .push(
+            widget::text_input(fl!("add_tag"), &app.tag_draft)
+                .on_input(Message::TagDraftChanged)
+                .width(Length::Fixed(160.)),
+        )
+        .push(
+            button(text(fl!("add")).size(14))
+                .padding(3)
+                .on_press(edit(MetadataEdit::AddTag(app.tag_draft.clone()))),
+        );
+    let hide_label = if meta.hidden {
+        fl!("restore")
+    } else {
+        fl!("hide")
+    };
+    let hide = button(text(hide_label).size(14))
+        .padding(3)
+        .on_press(edit(MetadataEdit::SetHidden(!meta.hidden)));
+    let mut content = widget::row()
+        .push(text(fl!("content")))
+        .spacing(4)
+        .align_y(Alignment::Center);
+    for (label, content_override) in content_override_options() {
+        content = content.push(rating_button(
+            label,
+            meta.content_override == content_override,
+            edit(MetadataEdit::SetContentOverride(content_override)),
+        ));
+    }
+    let notes_path = path.to_path_buf();
+    widget::column()
+        .push(
+            widget::row()
+                .push(
+                    widget::checkbox(fl!("favorite"), meta.favorite)
+                        .on_toggle(move |favorite| edit(MetadataEdit::SetFavorite(favorite))),
+                )
+                .push(rating)
+                .push(tags)
+                .push(content)
+                .push(hide)
+                .spacing(12)
+                .align_y(Alignment::Center),
+        )
+        .push(
+            widget::text_input(fl!("notes"), meta.notes).on_input(move |notes| {
+                Message::EditMetadata(notes_path.clone(), MetadataEdit::SetNotes(notes))
+            }),
+        )
+        .spacing(4)
+        .into()
+}
+pub fn content_rating_name(rating: ContentRating) -> String {
+    match rating {
+        ContentRating::General => fl!("content_general"),
+        ContentRating::ArtisticNudity => fl!("content_artistic"),
+        ContentRating::Explicit => fl!("content_explicit"),
+    }
+}
+/// Labels of the possible content overrides of a reference. `None` uses the rating of the folder.
+pub fn content_override_options() -> [(String, Option<ContentRating>); 4] {
+    [
+        (fl!("content_folder"), None),
+        (
+            content_rating_name(ContentRating::General),
+            Some(ContentRating::General),
+        ),
+        (
+            content_rating_name(ContentRating::ArtisticNudity),
+            Some(ContentRating::ArtisticNudity),
+        ),
+        (
+            content_rating_name(ContentRating::Explicit),
+            Some(ContentRating::Explicit),
+        ),
+    ]
+}
+/// Small button which shows in brackets whether it is the active choice.
+fn rating_button<'a>(
+    label: String,
+    active: bool,
+    message: app::Message,
+) -> Element<'a, app::Message> {
+    let label = if active { format!("[{label}]") } else { label };
+    button(text(label).size(14))
+        .padding(3)
+        .on_press(message)
+        .into()
+}
+/// Options of the export shown in the context drawer.
+pub fn export(app: &AppModel) -> Element<app::Message> {
+    let options = app.export_options;
+    let source = match app.export_source {
+        ExportSource::Browser => fl!("export_browser", count = app.browser.entries.len()),
+        ExportSource::Album(id) => app
+            .albums
+            .get(id)
+            .map(|album| album.name.clone())
+            .unwrap_or_default(),
+    };
+    let option_button = |label: String, active: bool, options: ExportOptions| {
+        let label = if active { format!("[{label}]") } else { label };
+        button(text(label).size(14))
+            .padding(3)
+            .on_press(Message::SetExportOptions(options))
+    };
+    let mut modes = widget::row().spacing(8);
+    for mode in ExportMode::ALL {
+        let label = match mode {
+            ExportMode::Symlink => fl!("export_symlink"),
+            ExportMode::Copy => fl!("export_copy"),
+        };
+        modes = modes.push(option_button(
+            label,
+            options.mode == mode,
+            ExportOptions { mode, ..options },
+        ));
+    }
+    let mut names = widget::row().spacing(8);
+    for scheme in ExportNames::ALL {
+        let label = match scheme {
+            ExportNames::Keep => fl!("export_keep_names"),
+            ExportNames::Flatten => fl!("export_flatten"),
+            ExportNames::Numbered => fl!("export_numbered"),
+        };
+        names = names.push(option_button(
+            label,
+            options.names == scheme,
+            ExportOptions {
+                names: scheme,
+                ..options
+            },
+        ));
+    }
+    widget::column()
+        .push(widget::text::heading(source))
+        .push(modes)
+        .push(names)
+        .push(button(text(fl!("export_to_folder"))).on_press(Message::Export))
+        .push_maybe(app.export_status.as_ref().map(text))
+        .spacing(12)
+        .into()
+}
+/// Settings shown in the context drawer.
+pub fn settings(app: &AppModel) -> Element<app::Message> {
+    let config = &app.config;
+    widget::column()
+        .push(widget::text::heading(fl!("decode_limits")))
+        .push(widget::text(fl!(
+            "max_image_megapixels",
+            megapixels = config.max_image_megapixels
+        )))
+        .push(
+            widget::slider(
+                8..=512,
+                config.max_image_megapixels,
+                Message::SetMaxImageMegapixels,
+            )
+            .step(8u32),
+        )
+        .push(widget::text(fl!(
+            "max_decode_memory",
+            mib = config.max_decode_memory_mib
+        )))
+        .push(
+            widget::slider(
+                256..=8192,
+                config.max_decode_memory_mib,
+                Message::SetMaxDecodeMemory,
+            )
+            .step(256u32),
+        )
+        .spacing(8)
+        .into()
+}
+/// The last background problem together with a button to dismiss it.
+pub fn warning_banner(app: &AppModel) -> Option<Element<app::Message>> {
+    let warning = app.warning.as_ref()?;
+    Some(
+        row![
+            widget::text(warning.as_str()).width(Fill),
+            button(widget::text("x")).on_press(Message::DismissWarning)
+        ]
+        .spacing(8)
+        .into(),
+    )
+}
 pub fn center_text(text: String) -> Element<'static, <AppModel as cosmic::Application>::Message> {
     widget::text::title1(text)
         .apply(widget::container)
@@ -0,0 +1,96 @@
+//! Live updates of the source folders while the app is running.
+
+use std::{collections::BTreeSet, path::PathBuf, time::Duration};
+
+use cosmic::iced::Subscription;
+use futures_util::SinkExt;
+use notify::{
+    event::{ModifyKind, RenameMode},
+    EventKind, RecursiveMode, Watcher,
+};
+use tracing::warn;
+
+use crate::app::Message;
+
+/// Changes are collected until nothing happened for this long, so copying a whole pose pack
+/// into a source folder updates the store once instead of once per file.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Files and folders that were created, modified, moved or deleted below the source folders.
+#[derive(Debug, Clone, Default)]
+pub struct SourceChanges {
+    /// Paths which have to be scanned again. Deleted paths are part of it as well.
+    pub paths: BTreeSet<PathBuf>,
+    /// Old and new path of files and folders that were moved.
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+}
+impl SourceChanges {
+    fn extend(&mut self, other: SourceChanges) {
+        self.paths.extend(other.paths);
+        self.renamed.extend(other.renamed);
+    }
+}
+
+/// Watches all `folders` recursively and emits their changes.
+/// The watcher is restarted whenever the watched folders change.
+pub fn subscription(folders: Vec<PathBuf>) -> Subscription<Message> {
+    struct SourceWatcher;
+    if folders.is_empty() {
+        return Subscription::none();
+    }
+    Subscription::run_with_id(
+        (std::any::TypeId::of::<SourceWatcher>(), folders.clone()),
+        cosmic::iced::stream::channel(4, move |mut output| async move {
+            let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+            let watcher = notify::recommended_watcher(
+                move |event: notify::Result<notify::Event>| match event {
+                    Ok(event) => {
+                        if let Some(changes) = to_changes(event) {
+                            let _ = sender.send(changes);
+                        }
+                    }
+                    Err(e) => warn!("Error while watching source folders {e:?}"),
+                },
+            );
+            let mut watcher = match watcher {
+                Ok(watcher) => watcher,
+                Err(e) => {
+                    warn!("Could not watch source folders, changes are only picked up on restart. Error {e:?}");
+                    return futures_util::future::pending().await;
+                }
+            };
+            for folder in &folders {
+                if let Err(e) = watcher.watch(folder, RecursiveMode::Recursive) {
+                    warn!("Could not watch source folder {folder:?} with error {e:?}");
+                }
+            }
+            while let Some(mut changes) = receiver.recv().await {
+                while let Ok(Some(more)) = tokio::time::timeout(DEBOUNCE, receiver.recv()).await {
+                    changes.extend(more);
+                }
+                if output.send(Message::SourcesChanged(changes)).await.is_err() {
+                    break;
+                }
+            }
+            futures_util::future::pending().await
+        }),
+    )
+}
+
+fn to_changes(event: notify::Event) -> Option<SourceChanges> {
+    match event.kind {
+        // reading files, like decoding a reference, or changing permissions does not change the store
+        EventKind::Access(_) | EventKind::Modify(ModifyKind::Metadata(_)) => None,
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+            let renamed = vec![(event.paths[0].clone(), event.paths[1].clone())];
+            Some(SourceChanges {
+                paths: event.paths.into_iter().collect(),
+                renamed,
+            })
+        }
+        _ => Some(SourceChanges {
+            paths: event.paths.into_iter().collect(),
+            renamed: Vec::new(),
+        }),
+    }
+}